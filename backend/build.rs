@@ -0,0 +1,37 @@
+// Versions of the PDF engines as resolved in Cargo.lock, recorded on every
+// document the engine extracts (`utils::extractor::registry`).
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    let lock = std::fs::read_to_string("Cargo.lock").expect("read Cargo.lock");
+    let package = std::env::var("CARGO_PKG_NAME").expect("CARGO_PKG_NAME");
+    for (dep, var) in [("lopdf", "LOPDF_VERSION"), ("pdf-extract", "PDF_EXTRACT_VERSION")] {
+        let version = resolved(&lock, &package, dep).unwrap_or_else(|| panic!("no `{dep}` in Cargo.lock"));
+        println!("cargo:rustc-env={var}={version}");
+    }
+}
+
+// The version of `dep` this package links. Its `dependencies` list names
+// the version only when several are locked (`"lopdf 0.32.0"`); otherwise
+// the one `[[package]]` entry of that name is it.
+fn resolved(lock: &str, package: &str, dep: &str) -> Option<String> {
+    let entries: Vec<(&str, &str, &str)> = lock
+        .split("[[package]]")
+        .filter_map(|entry| Some((field(entry, "name")?, field(entry, "version")?, entry)))
+        .collect();
+    let (_, _, ours) = entries.iter().find(|(name, _, entry)| *name == package && !entry.contains("\nsource = "))?;
+    let listed = ours
+        .lines()
+        .map(|l| l.trim().trim_end_matches(',').trim_matches('"'))
+        .find(|l| l.split(' ').next() == Some(dep))?;
+    match listed.split_once(' ') {
+        Some((_, version)) => Some(version.to_owned()),
+        None               => entries.iter().find(|(name, ..)| *name == dep).map(|(_, v, _)| (*v).to_owned()),
+    }
+}
+
+// `key = "value"` at the start of a line
+fn field<'a>(entry: &'a str, key: &str) -> Option<&'a str> {
+    entry
+        .lines()
+        .find_map(|l| l.strip_prefix(key)?.trim_start().strip_prefix('=')?.trim().strip_prefix('"')?.strip_suffix('"'))
+}
@@ -0,0 +1,223 @@
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use futures_util::io::Cursor;
+use futures_util::StreamExt;
+use mongodb::{bson::{doc, Document}, gridfs::GridFsBucket, Database};
+use std::{env, fs, io::Write, path::PathBuf, sync::Arc};
+use crate::utils::spool::Spooled;
+
+/// Where original uploads are kept, content-addressed by the SHA-256 hex of
+/// their bytes. Putting a blob that is already there is a no-op, so
+/// documents uploaded from the same file share it.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// stored on each document as `blob_store`
+    fn name(&self) -> &'static str;
+    /// the upload is shared, so a store can write it off the async
+    /// executor without copying it
+    async fn put(&self, hash: &str, bytes: Arc<Spooled>) -> Result<()>;
+    async fn get(&self, hash: &str) -> Result<Option<Vec<u8>>>;
+    async fn delete(&self, hash: &str) -> Result<()>;
+}
+
+/// The configured store, if any; shared with handlers as app data.
+pub type Blobs = Option<Arc<dyn BlobStore>>;
+
+/// `BLOB_STORE=gridfs` (bucket `originals`) or `BLOB_STORE=fs` (under
+/// `BLOB_DIR`, default `./blobs`); unset keeps no originals.
+pub fn from_env(db: &Database) -> Result<Blobs> {
+    match env::var("BLOB_STORE").ok().as_deref() {
+        None | Some("")   => Ok(None),
+        Some("gridfs")    => Ok(Some(Arc::new(GridFs::new(db)))),
+        Some("fs")        => {
+            let dir = env::var("BLOB_DIR").unwrap_or_else(|_| "./blobs".into());
+            Ok(Some(Arc::new(FileSystem::new(dir)?)))
+        }
+        Some(other) => bail!("unknown BLOB_STORE: {other} (gridfs or fs)"),
+    }
+}
+
+/// Drops the originals of documents whose `retain_until` has passed, once
+/// no other document still keeps the same file.
+pub async fn sweep(db: &Database, store: &dyn BlobStore) -> Result<usize> {
+    let docs = db.collection::<Document>("documents");
+    let expired = doc! {
+        "blob_store":   { "$exists": true },
+        "retain_until": { "$lte": mongodb::bson::DateTime::now() },
+    };
+    let mut cursor = docs.find(expired.clone(), None).await?;
+    let mut hashes = Vec::new();
+    while let Some(d) = cursor.next().await {
+        if let Ok(hash) = d?.get_str("file_hash") {
+            hashes.push(hash.to_owned());
+        }
+    }
+    docs.update_many(expired, doc! { "$unset": { "blob_store": "" } }, None).await?;
+
+    hashes.sort();
+    hashes.dedup();
+    let mut deleted = 0;
+    for hash in hashes {
+        let kept = doc! { "file_hash": &hash, "blob_store": { "$exists": true } };
+        if docs.count_documents(kept, None).await? == 0 {
+            store.delete(&hash).await?;
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+// hashes come from requests via stored records; never let one name a path
+fn check_hash(hash: &str) -> Result<()> {
+    if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        bail!("not a SHA-256 hex digest: {hash:?}");
+    }
+    Ok(())
+}
+
+/// GridFS bucket; the hash is the file name.
+pub struct GridFs {
+    bucket: GridFsBucket,
+}
+
+impl GridFs {
+    pub fn new(db: &Database) -> Self {
+        let options = mongodb::options::GridFsBucketOptions::builder().bucket_name("originals".to_owned()).build();
+        GridFs { bucket: db.gridfs_bucket(options) }
+    }
+
+    async fn ids(&self, hash: &str) -> Result<Vec<mongodb::bson::Bson>> {
+        let mut cursor = self.bucket.find(doc! { "filename": hash }, None).await?;
+        let mut ids = Vec::new();
+        while let Some(file) = cursor.next().await {
+            ids.push(file?.id);
+        }
+        Ok(ids)
+    }
+}
+
+#[async_trait]
+impl BlobStore for GridFs {
+    fn name(&self) -> &'static str { "gridfs" }
+
+    // a concurrent upload of the same file may leave two copies under one
+    // name; reads take the newest and deletes remove all
+    async fn put(&self, hash: &str, bytes: Arc<Spooled>) -> Result<()> {
+        check_hash(hash)?;
+        if !self.ids(hash).await?.is_empty() {
+            return Ok(());
+        }
+        self.bucket
+            .upload_from_futures_0_3_reader(hash, Cursor::new(&bytes[..]), None)
+            .await
+            .context("GridFS upload")?;
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        check_hash(hash)?;
+        if self.ids(hash).await?.is_empty() {
+            return Ok(None);
+        }
+        let mut out = Cursor::new(Vec::new());
+        self.bucket
+            .download_to_futures_0_3_writer_by_name(hash, &mut out, None)
+            .await
+            .context("GridFS download")?;
+        Ok(Some(out.into_inner()))
+    }
+
+    async fn delete(&self, hash: &str) -> Result<()> {
+        check_hash(hash)?;
+        for id in self.ids(hash).await? {
+            self.bucket.delete(id).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Plain files at `<dir>/<first two hex digits>/<hash>`.
+pub struct FileSystem {
+    dir: PathBuf,
+}
+
+impl FileSystem {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).with_context(|| format!("create {}", dir.display()))?;
+        Ok(FileSystem { dir })
+    }
+
+    fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(&hash[..2]).join(hash)
+    }
+}
+
+#[async_trait]
+impl BlobStore for FileSystem {
+    fn name(&self) -> &'static str { "fs" }
+
+    async fn put(&self, hash: &str, bytes: Arc<Spooled>) -> Result<()> {
+        check_hash(hash)?;
+        let path = self.path(hash);
+        // written aside and renamed in, so readers never see half a file
+        actix_web::rt::task::spawn_blocking(move || -> Result<()> {
+            if path.exists() {
+                return Ok(());
+            }
+            let dir = path.parent().context("blob path")?;
+            fs::create_dir_all(dir)?;
+            let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+            tmp.write_all(&bytes)?;
+            tmp.persist(&path)?;
+            Ok(())
+        })
+        .await?
+    }
+
+    async fn get(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        check_hash(hash)?;
+        let path = self.path(hash);
+        actix_web::rt::task::spawn_blocking(move || match fs::read(&path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        })
+        .await?
+    }
+
+    async fn delete(&self, hash: &str) -> Result<()> {
+        check_hash(hash)?;
+        let path = self.path(hash);
+        actix_web::rt::task::spawn_blocking(move || match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::spool::Spool;
+
+    #[actix_web::test]
+    async fn file_system_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileSystem::new(dir.path()).unwrap();
+        // past the memory limit, so the upload is a mapped temp file
+        let mut spool = Spool::new();
+        spool.write(&vec![7u8; crate::utils::spool::MEMORY_LIMIT + 1]).unwrap();
+        let bytes = Arc::new(spool.finish().unwrap());
+        let hash = crate::merkle::coreFunctions::generate_hash(&bytes[..]);
+
+        assert!(store.get(&hash).await.unwrap().is_none());
+        store.put(&hash, bytes.clone()).await.unwrap();
+        store.put(&hash, bytes.clone()).await.unwrap();
+        assert_eq!(store.get(&hash).await.unwrap().as_deref(), Some(&bytes[..]));
+        store.delete(&hash).await.unwrap();
+        assert!(store.get(&hash).await.unwrap().is_none());
+        assert!(store.put("../escape", bytes).await.is_err());
+    }
+}
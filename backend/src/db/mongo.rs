@@ -1,5 +1,4 @@
-use mongodb::{Client, Database, options::ClientOptions}; 
-use anyhow::Result; 
+use mongodb::{bson::{doc, Document}, Client, Database, IndexModel, options::{ClientOptions, IndexOptions}}; 
 use std::env; 
 
 pub async fn init() -> anyhow::Result<Database> {
@@ -12,4 +11,34 @@ pub async fn init() -> anyhow::Result<Database> {
 
     let client = Client::with_options(opts)?; 
     Ok(client.database(&db_name))
-}
\ No newline at end of file
+}
+
+/// Indexes the upload path looks duplicates up by, and the page lookup.
+/// Top-level uploads are marked `canonical`, so two identical uploads
+/// racing each other can't both be stored.
+pub async fn ensure_indexes(db: &Database) -> anyhow::Result<()> {
+    let docs = db.collection::<Document>("documents");
+    docs.create_indexes(
+        vec![
+            IndexModel::builder().keys(doc! { "root_hash": 1 }).build(),
+            IndexModel::builder().keys(doc! { "file_hash": 1, "root_hash": 1 }).build(),
+            IndexModel::builder()
+                .keys(doc! { "file_hash": 1, "root_hash": 1 })
+                .options(
+                    IndexOptions::builder()
+                        .name("canonical_file_hash_root_hash".to_owned())
+                        .unique(true)
+                        .partial_filter_expression(doc! { "canonical": true })
+                        .build(),
+                )
+                .build(),
+        ],
+        None,
+    )
+    .await?;
+    // reverse lookup: which documents contain a page
+    db.collection::<Document>("pages")
+        .create_index(IndexModel::builder().keys(doc! { "page_hash": 1 }).build(), None)
+        .await?;
+    Ok(())
+}
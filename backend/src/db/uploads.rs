@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use std::{env, io};
+use uuid::Uuid;
+
+/// Resumable uploads in progress, under `UPLOAD_DIR` (default `./uploads`):
+/// `<id>.part` holds the bytes received so far, `<id>.json` its `Info`.
+/// Uploads untouched for `UPLOAD_EXPIRY_HOURS` (default 24) are swept.
+pub struct Uploads {
+    dir:       PathBuf,
+    pub ttl:   Duration,
+    // passwords of encrypted PDFs are only ever held in memory
+    passwords: Mutex<HashMap<String, String>>,
+    busy:      Mutex<HashSet<String>>,
+}
+
+/// What an upload was created with.
+#[derive(Serialize, Deserialize)]
+pub struct Info {
+    pub length:   u64,
+    pub filename: String,
+    /// media type from `Upload-Metadata`, if the client sent one
+    pub filetype: Option<String>,
+    /// query string of the creating request: `POST /documents` options
+    pub query:    String,
+    /// SHA-256 hex the complete file must hash to
+    pub sha256:   Option<String>,
+    /// the `POST /documents` response, once the upload is ingested
+    pub result:   Option<serde_json::Value>,
+}
+
+/// Held while a request appends to an upload; one at a time.
+pub struct Lock<'a> {
+    uploads: &'a Uploads,
+    id:      String,
+}
+
+impl Drop for Lock<'_> {
+    fn drop(&mut self) {
+        self.uploads.busy.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl Uploads {
+    pub fn from_env() -> Result<Self> {
+        let dir = PathBuf::from(env::var("UPLOAD_DIR").unwrap_or_else(|_| "./uploads".into()));
+        let hours = env::var("UPLOAD_EXPIRY_HOURS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(24);
+        Uploads::new(dir, Duration::from_secs(hours * 3600))
+    }
+
+    pub fn new(dir: PathBuf, ttl: Duration) -> Result<Self> {
+        fs::create_dir_all(&dir).with_context(|| format!("create {}", dir.display()))?;
+        Ok(Uploads {
+            dir,
+            ttl,
+            passwords: Mutex::default(),
+            busy:      Mutex::default(),
+        })
+    }
+
+    pub fn create(&self, info: &Info, password: Option<String>) -> Result<String> {
+        let id = Uuid::new_v4().simple().to_string();
+        File::create(self.part(&id))?;
+        self.save(&id, info)?;
+        if let Some(pw) = password {
+            self.set_password(&id, pw);
+        }
+        Ok(id)
+    }
+
+    /// `None` for unknown ids, including ones that aren't ids at all.
+    pub fn info(&self, id: &str) -> Result<Option<Info>> {
+        if Uuid::try_parse(id).is_err() {
+            return Ok(None);
+        }
+        match fs::read(self.meta(id)) {
+            Ok(json) => Ok(Some(serde_json::from_slice(&json)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, id: &str, info: &Info) -> Result<()> {
+        fs::write(self.meta(id), serde_json::to_vec(info)?)?;
+        Ok(())
+    }
+
+    pub fn lock(&self, id: &str) -> Option<Lock<'_>> {
+        self.busy
+            .lock()
+            .unwrap()
+            .insert(id.to_owned())
+            .then(|| Lock { uploads: self, id: id.to_owned() })
+    }
+
+    /// Bytes received so far; the whole length once ingested.
+    pub fn offset(&self, id: &str, info: &Info) -> Result<u64> {
+        if info.result.is_some() {
+            return Ok(info.length);
+        }
+        Ok(fs::metadata(self.part(id))?.len())
+    }
+
+    /// The received bytes, for appending; only under the upload's `Lock`.
+    pub fn append(&self, id: &str, _lock: &Lock) -> Result<File> {
+        Ok(OpenOptions::new().append(true).open(self.part(id))?)
+    }
+
+    pub fn part(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.part"))
+    }
+
+    pub fn password(&self, id: &str) -> Option<String> {
+        self.passwords.lock().unwrap().get(id).cloned()
+    }
+
+    /// Held until the upload is finished, or the server restarts; a
+    /// client can send it again with any `PATCH`.
+    pub fn set_password(&self, id: &str, password: String) {
+        self.passwords.lock().unwrap().insert(id.to_owned(), password);
+    }
+
+    /// Drops the received bytes and password, keeping the record of an
+    /// ingested upload until it expires.
+    pub fn finish(&self, id: &str) -> Result<()> {
+        self.passwords.lock().unwrap().remove(id);
+        remove(self.part(id))
+    }
+
+    pub fn remove(&self, id: &str) -> Result<()> {
+        self.finish(id)?;
+        remove(self.meta(id))
+    }
+
+    /// When an upload expires unless it is touched again.
+    pub fn expires(&self, id: &str) -> SystemTime {
+        let touched = fs::metadata(self.part(id))
+            .or_else(|_| fs::metadata(self.meta(id)))
+            .and_then(|m| m.modified())
+            .unwrap_or_else(|_| SystemTime::now());
+        touched + self.ttl
+    }
+
+    pub fn sweep(&self) -> Result<usize> {
+        let now = SystemTime::now();
+        let mut removed = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let Some(id) = path.file_stem().and_then(|s| s.to_str()) else { continue };
+            if path.extension().is_some_and(|e| e == "json")
+                && self.expires(id) <= now
+                && !self.busy.lock().unwrap().contains(id)
+            {
+                self.remove(id)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn meta(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+}
+
+fn remove(path: PathBuf) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,263 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{bson::{doc, Document}, Database};
+use serde_json::json;
+use crate::handlers::documents::canonical_id;
+use crate::handlers::proof::{load_page_hashes, n_leaves};
+use crate::merkle::coreFunctions::{proof_from_levels, tree_levels};
+
+/// LCS table size past the common head and tail; ~40 MB of `u32`
+const MAX_CELLS: usize = 10_000_000;
+
+/// fields that decide what a leaf hash commits to
+const SETTINGS: [&str; 5] = ["extract_mode", "canon_profile", "chunk_size", "extractor", "member_options"];
+
+pub fn register(cfg: &mut web::ServiceConfig) {
+    cfg.route("/documents/{a}/diff/{b}", web::get().to(diff_route));
+}
+
+/// Aligns the leaf sequences of `{a}` and `{b}` by longest common
+/// subsequence: each leaf is `unchanged` (with its proofs against both
+/// roots), `deleted` from a, `inserted` in b, or `modified` where a
+/// deletion and an insertion meet. Both must have been hashed alike.
+async fn diff_route(path: web::Path<(String, String)>, db: web::Data<Database>) -> impl Responder {
+    let (a_id, b_id) = path.into_inner();
+    let mut sides = Vec::with_capacity(2);
+    for doc_id in [a_id, b_id] {
+        let doc_id = match canonical_id(&db, doc_id).await {
+            Ok(id)    => id,
+            Err(resp) => return resp,
+        };
+        match load_side(&db, doc_id).await {
+            Ok(side)  => sides.push(side),
+            Err(resp) => return resp,
+        }
+    }
+    let (a, b) = (&sides[0], &sides[1]);
+
+    if let Some(field) = SETTINGS.iter().find(|f| a.meta.get(f) != b.meta.get(f)) {
+        return HttpResponse::BadRequest()
+            .body(format!("documents were hashed with different `{field}`; their leaves don't compare"));
+    }
+    let Some(ops) = align(&a.hashes, &b.hashes) else {
+        return HttpResponse::BadRequest().body("documents differ too much to align page by page");
+    };
+
+    let mut pages = Vec::with_capacity(ops.len());
+    let mut counts = [0usize; 4];
+    for op in ops {
+        pages.push(match op {
+            Op::Same(i, j) => {
+                counts[0] += 1;
+                json!({
+                    "a_index":   i,
+                    "b_index":   j,
+                    "page_hash": a.hashes[i],
+                    "proof_a":   proof_from_levels(&a.levels, i),
+                    "proof_b":   proof_from_levels(&b.levels, j),
+                    "status":    "unchanged",
+                })
+            }
+            Op::Delete(i) => {
+                counts[1] += 1;
+                json!({ "a_index": i, "page_hash": a.hashes[i], "status": "deleted" })
+            }
+            Op::Insert(j) => {
+                counts[2] += 1;
+                json!({ "b_index": j, "page_hash": b.hashes[j], "status": "inserted" })
+            }
+            Op::Modify(i, j) => {
+                counts[3] += 1;
+                json!({
+                    "a_hash":  a.hashes[i],
+                    "a_index": i,
+                    "b_hash":  b.hashes[j],
+                    "b_index": j,
+                    "status":  "modified",
+                })
+            }
+        });
+    }
+
+    HttpResponse::Ok().json(json!({
+        "a":         a.summary(),
+        "b":         b.summary(),
+        "identical": a.root == b.root,
+        "pages":     pages,
+        "summary": {
+            "deleted":   counts[1],
+            "inserted":  counts[2],
+            "modified":  counts[3],
+            "unchanged": counts[0],
+        },
+    }))
+}
+
+struct Side {
+    doc_id: String,
+    meta:   Document,
+    root:   String,
+    hashes: Vec<String>,
+    // the tree over `hashes`, so each proof is a lookup
+    levels: Vec<Vec<String>>,
+}
+
+impl Side {
+    fn summary(&self) -> serde_json::Value {
+        json!({
+            "document_id": self.doc_id,
+            "filename":    self.meta.get_str("filename").ok(),
+            "n_leaves":    self.hashes.len(),
+            "n_pages":     self.meta.get_i32("n_pages").ok(),
+            "root_hash":   self.root,
+        })
+    }
+}
+
+async fn load_side(db: &Database, doc_id: String) -> Result<Side, HttpResponse> {
+    let meta = match db
+        .collection::<Document>("documents")
+        .find_one(doc! { "_id": &doc_id }, None)
+        .await
+    {
+        Ok(Some(d)) => d,
+        Ok(None)    => return Err(HttpResponse::NotFound().body(format!("document not found: {doc_id}"))),
+        Err(e)      => {
+            eprintln!("find document error: {e}");
+            return Err(HttpResponse::InternalServerError().body("db error"));
+        }
+    };
+    let hashes = match load_page_hashes(db, &doc_id, n_leaves(&meta)).await {
+        Ok(h)  => h,
+        Err(e) => {
+            eprintln!("load pages error: {e}");
+            return Err(HttpResponse::InternalServerError().body("db error"));
+        }
+    };
+    let root = meta.get_str("root_hash").unwrap_or_default().to_owned();
+    let levels = tree_levels(&hashes);
+    Ok(Side { doc_id, meta, root, hashes, levels })
+}
+
+enum Op {
+    Same(usize, usize),
+    Delete(usize),
+    Insert(usize),
+    Modify(usize, usize),
+}
+
+// LCS over what remains once the common head and tail are set aside;
+// within each gap between kept leaves, deletions and insertions pair up
+// as modifications in order. `None` when the table would be too large.
+fn align(a: &[String], b: &[String]) -> Option<Vec<Op>> {
+    let head = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let tail = a[head..].iter().rev().zip(b[head..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let (mid_a, mid_b) = (&a[head..a.len() - tail], &b[head..b.len() - tail]);
+    let (n, m) = (mid_a.len(), mid_b.len());
+    if (n + 1).saturating_mul(m + 1) > MAX_CELLS {
+        return None;
+    }
+
+    // lcs[i * (m + 1) + j]: LCS length of mid_a[i..] and mid_b[j..]
+    let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i * (m + 1) + j] = if mid_a[i] == mid_b[j] {
+                lcs[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+            };
+        }
+    }
+
+    let mut ops: Vec<Op> = (0..head).map(|k| Op::Same(k, k)).collect();
+    let (mut dels, mut ins) = (Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && mid_a[i] == mid_b[j] {
+            flush(&mut ops, &mut dels, &mut ins);
+            ops.push(Op::Same(head + i, head + j));
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1]) {
+            dels.push(head + i);
+            i += 1;
+        } else {
+            ins.push(head + j);
+            j += 1;
+        }
+    }
+    flush(&mut ops, &mut dels, &mut ins);
+    ops.extend((0..tail).map(|k| Op::Same(a.len() - tail + k, b.len() - tail + k)));
+    Some(ops)
+}
+
+fn flush(ops: &mut Vec<Op>, dels: &mut Vec<usize>, ins: &mut Vec<usize>) {
+    let paired = dels.len().min(ins.len());
+    ops.extend(dels.iter().zip(ins.iter()).map(|(&i, &j)| Op::Modify(i, j)));
+    ops.extend(dels[paired..].iter().map(|&i| Op::Delete(i)));
+    ops.extend(ins[paired..].iter().map(|&j| Op::Insert(j)));
+    dels.clear();
+    ins.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(s: &str) -> Vec<String> {
+        s.chars().map(String::from).collect()
+    }
+
+    // `=ij` same, `-i` deleted, `+j` inserted, `~ij` modified
+    fn script(a: &str, b: &str) -> String {
+        align(&leaves(a), &leaves(b))
+            .unwrap()
+            .iter()
+            .map(|op| match op {
+                Op::Same(i, j)   => format!("={i}{j}"),
+                Op::Delete(i)    => format!("-{i}"),
+                Op::Insert(j)    => format!("+{j}"),
+                Op::Modify(i, j) => format!("~{i}{j}"),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn identical_sides_are_all_head() {
+        assert_eq!(script("abc", "abc"), "=00 =11 =22");
+    }
+
+    #[test]
+    fn empty_sides() {
+        assert_eq!(script("", ""), "");
+        assert_eq!(script("", "ab"), "+0 +1");
+        assert_eq!(script("ab", ""), "-0 -1");
+    }
+
+    #[test]
+    fn head_and_tail_are_trimmed() {
+        assert_eq!(script("abcd", "acd"), "=00 -1 =21 =32");
+        assert_eq!(script("abc", "abxc"), "=00 =11 +2 =23");
+        // repeated leaves: the head takes what it can, the rest is deleted
+        assert_eq!(script("aaaa", "aa"), "=00 =11 -2 -3");
+    }
+
+    #[test]
+    fn deletions_and_insertions_pair_into_modified() {
+        assert_eq!(script("abcdef", "abXdeYf"), "=00 =11 ~22 =33 =44 +5 =56");
+        assert_eq!(script("abcd", "xbcy"), "~00 =11 =22 ~33");
+        // a longer run on one side leaves the excess unpaired
+        assert_eq!(script("axyb", "azb"), "=00 ~11 -2 =32");
+        assert_eq!(script("azb", "axyb"), "=00 ~11 +2 =23");
+    }
+
+    #[test]
+    fn oversized_middle_is_refused() {
+        let a: Vec<String> = (0..4000).map(|i| format!("a{i}")).collect();
+        let b: Vec<String> = (0..4000).map(|i| format!("b{i}")).collect();
+        assert!(align(&a, &b).is_none());
+        // the shared head and tail don't count against the table
+        assert_eq!(align(&a, &a).map(|ops| ops.len()), Some(4000));
+    }
+}
@@ -0,0 +1,130 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{bson::{doc, Document}, Database};
+use serde::{Deserialize, Serialize};
+use crate::handlers::documents::canonical_id;
+use crate::handlers::proof::{load_page_hashes, n_leaves};
+use crate::merkle::coreFunctions::generate_proof_for_index;
+
+#[derive(Deserialize)]
+struct FieldQuery {
+    pointer: Option<String>,   // JSON: RFC 6901, e.g. /totals/0/amount
+    name:    Option<String>,   // PDF forms: fully qualified field name
+}
+
+#[derive(Serialize)]
+struct DisclosureResponse {
+    key:        String,
+    preimage:   String,                 // hash this to get page_hash
+    page_hash:  String,
+    page_index: usize,
+    root_hash:  String,
+    proof:      Vec<(String, String)>,  // bottom‑up sibling list
+}
+
+pub fn register(cfg: &mut web::ServiceConfig) {
+    cfg.route("/documents/{id}/fields", web::get().to(field_route));
+    cfg.route("/documents/{id}/rows/{row}", web::get().to(row_route));
+    cfg.route("/documents/{id}/leaves/{key}", web::get().to(leaf_route));
+    cfg.route("/documents/{id}/metadata/proof", web::get().to(metadata_route));
+    cfg.route("/documents/{id}/signatures/proof", web::get().to(signatures_route));
+}
+
+async fn field_route(
+    path:  web::Path<String>,
+    query: web::Query<FieldQuery>,
+    db:    web::Data<Database>,
+) -> impl Responder {
+    let key = match query.into_inner() {
+        FieldQuery { pointer: Some(pointer), name: None } => pointer,
+        FieldQuery { pointer: None, name: Some(name) }    => format!("field:{name}"),
+        _ => return HttpResponse::BadRequest().body("pass exactly one of pointer= or name="),
+    };
+    disclose(&db, &path.into_inner(), &key).await
+}
+
+async fn row_route(
+    path: web::Path<(String, usize)>,
+    db:   web::Data<Database>,
+) -> impl Responder {
+    let (doc_id, row) = path.into_inner();
+    disclose(&db, &doc_id, &row.to_string()).await
+}
+
+// any keyed leaf, e.g. `header:From` or `attachment:0` of an e-mail
+async fn leaf_route(
+    path: web::Path<(String, String)>,
+    db:   web::Data<Database>,
+) -> impl Responder {
+    let (doc_id, key) = path.into_inner();
+    disclose(&db, &doc_id, &key).await
+}
+
+// the canonical Info/XMP record of a PDF uploaded with `metadata=true`
+async fn metadata_route(
+    path: web::Path<String>,
+    db:   web::Data<Database>,
+) -> impl Responder {
+    disclose(&db, &path.into_inner(), "metadata").await
+}
+
+// the embedded-signature report of a signed PDF
+async fn signatures_route(
+    path: web::Path<String>,
+    db:   web::Data<Database>,
+) -> impl Responder {
+    disclose(&db, &path.into_inner(), "signatures").await
+}
+
+/// Reveals one keyed leaf (JSON field, CSV row, e-mail header …) with its
+/// inclusion proof; nothing else of the document leaves the server.
+pub(crate) async fn disclose(db: &Database, doc_id: &str, key: &str) -> HttpResponse {
+    let doc_id = match canonical_id(db, doc_id.to_owned()).await {
+        Ok(id)    => id,
+        Err(resp) => return resp,
+    };
+    let doc_id = doc_id.as_str();
+    let docs_coll  = db.collection::<Document>("documents");
+    let pages_coll = db.collection::<Document>("pages");
+
+    let doc_meta = match docs_coll.find_one(doc! { "_id": doc_id }, None).await {
+        Ok(Some(d)) => d,
+        Ok(None)    => return HttpResponse::NotFound().body("document not found"),
+        Err(e)      => {
+            eprintln!("find document error: {e}");
+            return HttpResponse::InternalServerError().body("db error");
+        }
+    };
+    let page = match pages_coll.find_one(doc! { "document_id": doc_id, "key": key }, None).await {
+        Ok(Some(p)) => p,
+        Ok(None)    => return HttpResponse::NotFound().body(format!("no leaf with key {key:?}")),
+        Err(e)      => {
+            eprintln!("find page error: {e}");
+            return HttpResponse::InternalServerError().body("db error");
+        }
+    };
+
+    let n_leaves   = n_leaves(&doc_meta);
+    let page_hashes = match load_page_hashes(db, doc_id, n_leaves).await {
+        Ok(h)  => h,
+        Err(e) => {
+            eprintln!("load pages error: {e}");
+            return HttpResponse::InternalServerError().body("db error");
+        }
+    };
+    // a record from before `n_leaves`, or a broken one, may place the leaf
+    // past the end of its tree
+    let index = usize::try_from(page.get_i32("page_index").unwrap_or(-1)).ok();
+    let Some((page_index, page_hash)) = index.and_then(|i| Some((i, page_hashes.get(i)?.clone()))) else {
+        eprintln!("disclose: leaf {key:?} of {doc_id} is not among its {n_leaves} leaves");
+        return HttpResponse::InternalServerError().body("leaf index out of range for this document");
+    };
+
+    HttpResponse::Ok().json(DisclosureResponse {
+        key:        key.to_owned(),
+        preimage:   page.get_str("preimage").unwrap_or_default().to_owned(),
+        page_hash,
+        page_index,
+        root_hash:  doc_meta.get_str("root_hash").unwrap_or_default().to_owned(),
+        proof:      generate_proof_for_index(&page_hashes, page_index),
+    })
+}
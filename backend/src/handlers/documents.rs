@@ -1,110 +1,846 @@
 use actix_multipart::Multipart;
 use actix_web::{web, HttpResponse, Responder};
 use futures_util::StreamExt;
-use mongodb::{bson::doc, Database};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::{bson::{doc, Document}, options::FindOneOptions, Collection, Database};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
 use uuid::Uuid;
+use crate::db::blob::Blobs;
 use crate::merkle::coreFunctions::generate_hash;
-use crate::merkle::coreFunctions::build_tree_from_hashes;
+use crate::utils::canonical::Profile;
+use crate::utils::spool::{Spool, Spooled};
+use crate::utils::extractor::{self, Mode, Source, Unit};
 
 pub fn register(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/documents")
             .route(web::post().to(upload_document)),
     );
+    cfg.route("/documents/{id}/original", web::get().to(original_route));
+}
+
+#[derive(Deserialize)]
+pub(crate) struct UploadOptions {
+    /// canonicalization profile id, see `utils::canonical::Profile`
+    profile: Option<String>,
+    /// leaf source, see `utils::extractor::Mode` (default: text)
+    mode:    Option<String>,
+    /// raw mode only, bytes per leaf (default: `RAW_CHUNK_SIZE` or 1 MiB)
+    chunk_size: Option<usize>,
+    /// PDFs: also commit the Info/XMP metadata as a leaf
+    #[serde(default)]
+    metadata:   bool,
+    /// engine name, see `utils::extractor::EXTRACTORS` (default: by format)
+    extractor:  Option<String>,
+    /// days to keep the original when `BLOB_STORE` is set (default: for
+    /// good; 0: don't keep it)
+    retain_days: Option<u32>,
+    /// same file, same root as a stored document: `existing` (default)
+    /// returns that document, `link` adds a record pointing at it
+    on_duplicate: Option<String>,
 }
 
 async fn upload_document(
     mut payload: Multipart,
+    opts: web::Query<UploadOptions>,
     db: web::Data<Database>,
+    blobs: web::Data<Blobs>,
 ) -> impl Responder {
+    let intake = match intake(&opts) {
+        Ok(i)  => i,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+
     // 1) read multipart into bytes & keep original filename
-    let mut bytes = web::BytesMut::new();
+    let upload = match read_upload(&mut payload).await {
+        Ok(u)     => u,
+        Err(resp) => return resp,
+    };
+    match ingest(&db, &blobs, &upload, &intake).await {
+        Ok(body)  => HttpResponse::Ok().json(body),
+        Err(resp) => resp,
+    }
+}
+
+/// `POST /documents` options, checked before any bytes are read.
+pub(crate) struct Intake {
+    pub opts:        extractor::Options,
+    retain_days:     Option<u32>,
+    link_duplicates: bool,
+}
+
+pub(crate) fn intake(opts: &UploadOptions) -> Result<Intake, String> {
+    let profile = match opts.profile.as_deref() {
+        None     => Profile::DEFAULT,
+        Some(id) => match Profile::from_id(id) {
+            Some(p) => p,
+            None    => return Err(format!("unknown profile: {id}")),
+        },
+    };
+    let mode = match opts.mode.as_deref() {
+        None     => Mode::Text,
+        Some(id) => match Mode::from_id(id) {
+            Some(m) => m,
+            None    => return Err(format!("unknown mode: {id}")),
+        },
+    };
+    let chunk_size = opts.chunk_size.unwrap_or_else(|| {
+        env::var("RAW_CHUNK_SIZE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(extractor::DEFAULT_CHUNK_SIZE)
+    });
+    if chunk_size == 0 || chunk_size > extractor::MAX_CHUNK_SIZE {
+        return Err(format!("chunk_size must be 1‥{}", extractor::MAX_CHUNK_SIZE));
+    }
+
+    let engine = match opts.extractor.as_deref() {
+        None       => None,
+        Some(name) => match extractor::extractor_by_name(name) {
+            Some(e) => Some(e.name()),
+            None    => {
+                let known: Vec<&str> = extractor::EXTRACTORS.iter().map(|e| e.name()).collect();
+                return Err(format!("unknown extractor: {name} (one of {})", known.join(", ")));
+            }
+        },
+    };
+
+    let link_duplicates = match opts.on_duplicate.as_deref() {
+        None | Some("existing") => false,
+        Some("link")            => true,
+        Some(other)             => return Err(format!("unknown on_duplicate: {other} (existing or link)")),
+    };
+
+    Ok(Intake {
+        opts: extractor::Options { mode, profile, chunk_size, metadata: opts.metadata, extractor: engine },
+        retain_days: opts.retain_days,
+        link_duplicates,
+    })
+}
+
+/// Extracts, stores and records a complete upload; the body of the
+/// `POST /documents` response.
+pub(crate) async fn ingest(
+    db:     &Database,
+    blobs:  &Blobs,
+    upload: &Upload,
+    intake: &Intake,
+) -> Result<serde_json::Value, HttpResponse> {
+    let Intake { opts, retain_days, link_duplicates } = *intake;
+    let (profile, chunk_size) = (opts.profile, opts.chunk_size);
+    let filename = upload.filename.clone();
+
+    let keep = blobs.as_ref().filter(|_| retain_days != Some(0));
+    let retain_until = retain_days.map(|days| {
+        bson::DateTime::from_millis(bson::DateTime::now().timestamp_millis() + i64::from(days) * 86_400_000)
+    });
+
+    // hashing and extraction read the whole file: on the blocking pool
+    let prepared = {
+        let upload = upload.clone();
+        web::block(move || prepare(&upload, &opts)).await
+    };
+    let (file_hash, encrypted, extracted) = match prepared {
+        Ok(Ok(p))  => p,
+        Ok(Err(e)) => return Err(HttpResponse::BadRequest().body(format!("extract error: {e:#}"))),
+        Err(e)     => {
+            eprintln!("extract error: {e}");
+            return Err(HttpResponse::InternalServerError().body("extract error"));
+        }
+    };
+
+    // 3) write to Mongo  (documents & pages collections)
+    let docs_coll  = db.collection::<Document>("documents");
+    let pages_coll = db.collection::<Document>("pages");
+
+    // the same file extracted to the same root is stored once
+    let mut existing = match find_duplicate(&docs_coll, &file_hash, &extracted.root).await {
+        Ok(d)  => d,
+        Err(e) => {
+            eprintln!("find document error: {e}");
+            return Err(HttpResponse::InternalServerError().body("db error"));
+        }
+    };
+
+    // let doc_id = Uuid::new_v4();            // human‑readable UUID for response
+    // let mongo_id = mongodb::bson::Uuid::from_uuid(doc_id); // BSON binary subtype 4
+
+    let mut doc_id = Uuid::new_v4().to_string();
+    let mut fresh = None;
+    if existing.is_none() {
+        let mut doc_records  = Vec::new();
+        let mut page_records = Vec::new();
+        let members = collect_records(
+            &doc_id, &filename, &extracted, &opts, None,
+            &mut doc_records, &mut page_records,
+        );
+        if encrypted {
+            doc_records[0].insert("encrypted", true);
+        }
+        doc_records[0].insert("file_hash", &file_hash);
+        doc_records[0].insert("canonical", true);
+        if let Some(store) = keep {
+            if let Err(e) = store.put(&file_hash, upload.bytes.clone()).await {
+                eprintln!("blob store error: {e:#}");
+                return Err(HttpResponse::InternalServerError().body("blob store error"));
+            }
+            doc_records[0].insert("blob_store", store.name());
+            if let Some(until) = retain_until {
+                doc_records[0].insert("retain_until", until);
+            }
+        }
+
+        // the top-level record goes first, so losing the race to an
+        // identical upload inserts nothing: that one is the duplicate
+        match docs_coll.insert_many(doc_records, None).await {
+            Ok(_) => {
+                if let Err(e) = pages_coll.insert_many(page_records, None).await {
+                    eprintln!("insert pages error: {e}");
+                    return Err(HttpResponse::InternalServerError().body("db error"));
+                }
+                fresh = Some(members);
+            }
+            Err(e) if is_duplicate_key(&e) => {
+                existing = match find_duplicate(&docs_coll, &file_hash, &extracted.root).await {
+                    Ok(Some(d)) => Some(d),
+                    Ok(None)    => {
+                        eprintln!("insert document error: {e}");
+                        return Err(HttpResponse::InternalServerError().body("db error"));
+                    }
+                    Err(e) => {
+                        eprintln!("find document error: {e}");
+                        return Err(HttpResponse::InternalServerError().body("db error"));
+                    }
+                };
+            }
+            Err(e) => {
+                eprintln!("insert document error: {e}");
+                return Err(HttpResponse::InternalServerError().body("db error"));
+            }
+        }
+    }
+
+    let duplicate_of = existing.as_ref().and_then(|d| d.get_str("_id").ok()).map(str::to_owned);
+    let stored;
+    let members = match (fresh, &existing, &duplicate_of) {
+        (Some(members), _, _) => {
+            stored = keep.is_some();
+            members
+        }
+        (None, Some(existing), Some(original)) => {
+            stored = keep.is_some() || existing.contains_key("blob_store");
+            if let Some(store) = keep {
+                // recorded first: a sweep running meanwhile then still sees
+                // the file as kept and leaves the blob alone
+                let until = kept_until(existing, retain_until);
+                let update = match until {
+                    Some(until) => doc! { "$set": { "blob_store": store.name(), "retain_until": until } },
+                    None        => doc! { "$set": { "blob_store": store.name() }, "$unset": { "retain_until": "" } },
+                };
+                if let Err(e) = docs_coll.update_one(doc! { "_id": original }, update, None).await {
+                    eprintln!("update document error: {e}");
+                    return Err(HttpResponse::InternalServerError().body("db error"));
+                }
+                if let Err(e) = store.put(&file_hash, upload.bytes.clone()).await {
+                    eprintln!("blob store error: {e:#}");
+                    return Err(HttpResponse::InternalServerError().body("blob store error"));
+                }
+            }
+            if link_duplicates {
+                let link = doc! {
+                    "_id":          &doc_id,
+                    "filename":     &filename,
+                    "duplicate_of": original,
+                    "file_hash":    &file_hash,
+                    "root_hash":    &extracted.root,
+                    "n_pages":      extracted.n_pages() as i32,
+                    "n_leaves":     extracted.leaves.len() as i32,
+                    "format":       extracted.format.id(),
+                    "created_at":   bson::DateTime::now(),
+                };
+                if let Err(e) = docs_coll.insert_one(link, None).await {
+                    eprintln!("insert document error: {e}");
+                    return Err(HttpResponse::InternalServerError().body("db error"));
+                }
+            } else {
+                doc_id = original.clone();
+            }
+            match stored_members(&docs_coll, original, &extracted).await {
+                Ok(m)  => m,
+                Err(e) => {
+                    eprintln!("find members error: {e}");
+                    return Err(HttpResponse::InternalServerError().body("db error"));
+                }
+            }
+        }
+        _ => {
+            eprintln!("duplicate document without an id");
+            return Err(HttpResponse::InternalServerError().body("db error"));
+        }
+    };
+
+    // 4) respond
+    Ok(serde_json::json!({
+        "document_id": doc_id,
+        "root_hash":   extracted.root,
+        "n_pages":     extracted.n_pages(),
+        "n_leaves":    extracted.leaves.len(),
+        "format":      extracted.format.id(),
+        "unit":        extracted.unit.id(),
+        "image_pages": extracted.leaves.iter().filter(|p| p.source == Source::Images).count(),
+        "extract_mode": (!is_archive(&extracted)).then(|| extracted.mode.id()),
+        "extractor":   extracted.extractor,
+        "extractor_version": extracted.extractor_version,
+        "canon_profile": (extracted.mode == Mode::Text && !is_archive(&extracted)).then(|| profile.id()),
+        "chunk_size":  (extracted.mode == Mode::Raw).then_some(chunk_size),
+        "member_options": is_archive(&extracted).then(|| member_options(&opts)),
+        "metadata_index": extracted.leaves.iter().position(|l| l.source == Source::Metadata),
+        "signatures_index": extracted.leaves.iter().position(|l| l.source == Source::Signatures),
+        "template_root": template_root(&extracted),
+        "signatures":  (!extracted.signatures.is_empty()).then_some(&extracted.signatures),
+        "stamped":     extracted.stamped,
+        "encrypted":   encrypted,
+        "file_hash":   file_hash,
+        "stored":      stored,
+        "duplicate_of": duplicate_of,
+        "report":      leaf_report(&extracted),
+        "members":     (!members.is_empty()).then_some(members)
+    }))
+}
+
+// What was committed for each leaf, in leaf order.
+fn leaf_report(ex: &extractor::Extracted) -> Vec<serde_json::Value> {
+    ex.leaves
+        .iter()
+        .zip(&ex.hashes)
+        .enumerate()
+        .map(|(index, (leaf, hash))| {
+            let mut entry = serde_json::json!({
+                "index":  index,
+                "status": leaf.status.id(),
+                "source": leaf.source.id(),
+                "hash":   hash,
+            });
+            if let Some(key) = &leaf.key {
+                entry["key"] = key.as_str().into();
+            }
+            if let Some(error) = &leaf.error {
+                entry["error"] = error.as_str().into();
+            }
+            entry
+        })
+        .collect()
+}
+
+/// A multipart upload: the `file` field's bytes, name and type, plus the
+/// optional `password` field for encrypted PDFs, which is never stored.
+#[derive(Clone)]
+pub(crate) struct Upload {
+    pub bytes:        Arc<Spooled>,
+    pub filename:     String,
+    pub content_type: Option<String>,
+    pub password:     Option<String>,
+}
+
+impl Upload {
+    /// Name the format is detected by: sent as `message/rfc822`, the file
+    /// is an e-mail whatever it is called.
+    pub(crate) fn detect_name(&self) -> Cow<'_, str> {
+        match self.content_type.as_deref() {
+            Some(t) if t.eq_ignore_ascii_case("message/rfc822") => Cow::Owned(format!("{}.eml", self.filename)),
+            _ => Cow::Borrowed(&self.filename),
+        }
+    }
+}
+
+const MAX_PASSWORD_LEN: usize = 1024;
+const DEFAULT_MAX_UPLOAD_SIZE: usize = 256 * 1024 * 1024;
+
+/// `MAX_UPLOAD_SIZE`, for multipart and resumable uploads alike.
+pub(crate) fn max_upload_size() -> usize {
+    env::var("MAX_UPLOAD_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_UPLOAD_SIZE)
+}
+
+/// Reads the multipart body: exactly one `file` field, at most
+/// `MAX_UPLOAD_SIZE` bytes (default 256 MiB), spooled to disk when large,
+/// and optionally `password`. Anything else is rejected.
+pub(crate) async fn read_upload(payload: &mut Multipart) -> Result<Upload, HttpResponse> {
+    let max_size = max_upload_size();
+    let mut file: Option<Spool> = None;
     let mut filename = "upload.bin".to_owned();
+    let mut content_type = None;
+    let mut password: Option<Vec<u8>> = None;
 
     while let Some(item) = payload.next().await {
         let mut field = match item {
             Ok(f) => f,
-            Err(e) => return HttpResponse::BadRequest().body(format!("multipart error: {e}")),
+            Err(e) => return Err(HttpResponse::BadRequest().body(format!("multipart error: {e}"))),
         };
 
-        if let Some(fname) = field.content_disposition().get_filename() {
-            filename = fname.to_owned();
+        let is_password = match field.name() {
+            "password" if password.is_none() => true,
+            "file" if file.is_none()         => false,
+            "password" | "file" => {
+                return Err(HttpResponse::BadRequest()
+                    .body(format!("more than one `{}` field; send one file per upload", field.name())));
+            }
+            other => {
+                return Err(HttpResponse::BadRequest()
+                    .body(format!("unexpected field `{other}`; expected `file` and optionally `password`")));
+            }
+        };
+        if is_password {
+            password = Some(Vec::new());
+        } else {
+            if let Some(fname) = field.content_disposition().get_filename() {
+                filename = fname.to_owned();
+            }
+            content_type = field.content_type().map(|m| m.essence_str().to_owned());
+            file = Some(Spool::new());
         }
 
         while let Some(chunk) = field.next().await {
-            match chunk {
-                Ok(data) => bytes.extend_from_slice(&data),
-                Err(e) => return HttpResponse::BadRequest().body(format!("stream error: {e}")),
+            let data = match chunk {
+                Ok(data) => data,
+                Err(e) => return Err(HttpResponse::BadRequest().body(format!("stream error: {e}"))),
+            };
+            match (&mut password, &mut file) {
+                (Some(pw), _) if is_password => {
+                    if pw.len() + data.len() > MAX_PASSWORD_LEN {
+                        return Err(HttpResponse::BadRequest().body("password too long"));
+                    }
+                    pw.extend_from_slice(&data);
+                }
+                (_, Some(spool)) => {
+                    if spool.len() + data.len() > max_size {
+                        return Err(HttpResponse::PayloadTooLarge()
+                            .body(format!("file is larger than {max_size} bytes (MAX_UPLOAD_SIZE)")));
+                    }
+                    if let Err(e) = spool.write(&data) {
+                        eprintln!("spool error: {e}");
+                        return Err(HttpResponse::InternalServerError().body("spool error"));
+                    }
+                }
+                _ => unreachable!(),
             }
         }
     }
 
-    // 2) extract pages
-    let pages = match crate::utils::extractor::pdf_to_pages(&bytes) {
-        Ok(p)  => p,
-        Err(e) => return HttpResponse::BadRequest().body(format!("extract error: {e}")),
+    let bytes = match file.map(Spool::finish) {
+        Some(Ok(bytes)) => bytes,
+        Some(Err(e))    => {
+            eprintln!("spool error: {e}");
+            return Err(HttpResponse::InternalServerError().body("spool error"));
+        }
+        None => return Err(HttpResponse::BadRequest().body("missing `file` field")),
     };
-    if pages.is_empty() {
-        return HttpResponse::BadRequest().body("no text extracted");
+    let password = match password.map(String::from_utf8) {
+        Some(Ok(pw)) => Some(pw),
+        Some(Err(_)) => return Err(HttpResponse::BadRequest().body("password must be UTF-8")),
+        None         => None,
+    };
+    Ok(Upload { bytes: Arc::new(bytes), filename, content_type, password })
+}
+
+// The file hash, whether the file was encrypted, and its leaves. The
+// original is kept as uploaded, still encrypted if it was; encrypted PDFs
+// are decrypted in memory and the password goes no further.
+fn prepare(upload: &Upload, opts: &extractor::Options) -> anyhow::Result<(String, bool, extractor::Extracted)> {
+    let file_hash = generate_hash(&upload.bytes[..]);
+    let decrypted = decrypt_upload(upload, opts.mode)?;
+    let bytes: &[u8] = decrypted.as_deref().unwrap_or(&upload.bytes);
+    // 2) detect the container, derive leaves (archives: one set per member)
+    let extracted = extractor::extract(bytes, &upload.detect_name(), opts)?;
+    Ok((file_hash, decrypted.is_some(), extracted))
+}
+
+/// The plaintext of an encrypted PDF upload, for extraction only; `None`
+/// when there is nothing to decrypt.
+pub(crate) fn decrypt_upload(upload: &Upload, mode: Mode) -> anyhow::Result<Option<Vec<u8>>> {
+    if mode == Mode::Raw || extractor::Format::detect(&upload.bytes) != extractor::Format::Pdf {
+        return Ok(None);
+    }
+    extractor::decrypt_pdf(&upload.bytes, upload.password.as_deref())
+}
+
+/// Whether `decrypt_upload` fails for want of the right password, which
+/// the client can still send.
+pub(crate) fn needs_password(upload: &Upload, mode: Mode) -> bool {
+    mode != Mode::Raw
+        && extractor::Format::detect(&upload.bytes) == extractor::Format::Pdf
+        && extractor::needs_password(&upload.bytes, upload.password.as_deref())
+}
+
+// The first top-level upload of this file with this root; linked records
+// and archive members don't count.
+async fn find_duplicate(docs: &Collection<Document>, file_hash: &str, root: &str) -> mongodb::error::Result<Option<Document>> {
+    let filter = doc! {
+        "file_hash":    file_hash,
+        "root_hash":    root,
+        "parent_id":    { "$exists": false },
+        "duplicate_of": { "$exists": false },
+    };
+    let first = FindOneOptions::builder().sort(doc! { "created_at": 1 }).build();
+    docs.find_one(filter, first).await
+}
+
+// How long the original of a duplicate is kept once it asks to keep it
+// until `asked`: for good if either upload wants that, else the later date.
+fn kept_until(existing: &Document, asked: Option<bson::DateTime>) -> Option<bson::DateTime> {
+    let held = existing.contains_key("blob_store").then(|| existing.get_datetime("retain_until").ok().copied());
+    match (held, asked) {
+        (Some(None), _) | (_, None)     => None,
+        (Some(Some(held)), Some(asked)) => Some(held.max(asked)),
+        (None, Some(asked))             => Some(asked),
     }
+}
 
-    // println!("{:?}", pages); 
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    match &*e.kind {
+        ErrorKind::BulkWrite(f) => f.write_errors.iter().flatten().any(|w| w.code == 11000),
+        ErrorKind::Write(WriteFailure::WriteError(w)) => w.code == 11000,
+        _ => false,
+    }
+}
 
-    // 3) hash each page, build Merkle root
-    let page_hashes: Vec<String> = pages
+// Member summaries of an already stored upload, with the ids it was
+// stored under.
+async fn stored_members(
+    docs:     &Collection<Document>,
+    doc_id:   &str,
+    ex:       &extractor::Extracted,
+) -> mongodb::error::Result<Vec<serde_json::Value>> {
+    let mut cursor = docs.find(doc! { "parent_id": doc_id }, None).await?;
+    let mut ids = HashMap::new();
+    while let Some(d) = cursor.next().await {
+        let d = d?;
+        if let (Ok(index), Ok(id)) = (d.get_i32("parent_index"), d.get_str("_id")) {
+            ids.insert(index as usize, id.to_owned());
+        }
+    }
+    Ok(ex
+        .members
         .iter()
-        .map(|txt| generate_hash(txt))
-        .collect();
+        .filter_map(|m| ids.get(&m.index).map(|id| member_summary(m, id)))
+        .collect())
+}
 
-        // println!("{:?}", page_hashes); 
+/// Documents uploaded with `on_duplicate=link` hold no pages; routes that
+/// read pages follow them to the document they duplicate.
+pub(crate) async fn canonical_id(db: &Database, doc_id: String) -> Result<String, HttpResponse> {
+    match db.collection::<Document>("documents").find_one(doc! { "_id": &doc_id }, None).await {
+        Ok(Some(d)) => Ok(d.get_str("duplicate_of").map(str::to_owned).unwrap_or(doc_id)),
+        Ok(None)    => Ok(doc_id),
+        Err(e)      => {
+            eprintln!("find document error: {e}");
+            Err(HttpResponse::InternalServerError().body("db error"))
+        }
+    }
+}
 
-        let root = build_tree_from_hashes(page_hashes.clone());
+/// The original of a document as it was uploaded, when the blob store
+/// still keeps it. Archive members and attachments live inside their
+/// container's original.
+pub(crate) async fn stored_original(blobs: &Blobs, doc: &Document) -> Result<Vec<u8>, HttpResponse> {
+    let kept_in = doc.get_str("blob_store").ok();
+    let store = match blobs {
+        Some(store) if kept_in == Some(store.name()) => store,
+        _ => return Err(HttpResponse::NotFound().body("original not stored; upload it instead")),
+    };
+    match store.get(doc.get_str("file_hash").unwrap_or_default()).await {
+        Ok(Some(bytes)) => Ok(bytes),
+        Ok(None)        => Err(HttpResponse::NotFound().body("original no longer stored; upload it instead")),
+        Err(e)          => {
+            eprintln!("blob store error: {e:#}");
+            Err(HttpResponse::InternalServerError().body("blob store error"))
+        }
+    }
+}
 
-    // 4) write to Mongo  (documents & pages collections)
-    let docs_coll  = db.collection("documents");
-    let pages_coll = db.collection("pages");
+async fn original_route(
+    path:  web::Path<String>,
+    db:    web::Data<Database>,
+    blobs: web::Data<Blobs>,
+) -> impl Responder {
+    let doc_id = match canonical_id(&db, path.into_inner()).await {
+        Ok(id)    => id,
+        Err(resp) => return resp,
+    };
+    let doc_meta = match db
+        .collection::<Document>("documents")
+        .find_one(doc! { "_id": doc_id }, None)
+        .await
+    {
+        Ok(Some(d)) => d,
+        Ok(None)    => return HttpResponse::NotFound().body("document not found"),
+        Err(e)      => {
+            eprintln!("find document error: {e}");
+            return HttpResponse::InternalServerError().body("db error");
+        }
+    };
+    let bytes = match stored_original(&blobs, &doc_meta).await {
+        Ok(b)     => b,
+        Err(resp) => return resp,
+    };
+    let filename = doc_meta.get_str("filename").unwrap_or("original").replace('"', "");
+    HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{filename}\"")))
+        .insert_header(("X-File-Hash", doc_meta.get_str("file_hash").unwrap_or_default()))
+        .body(bytes)
+}
 
-    // let doc_id = Uuid::new_v4();            // human‑readable UUID for response
-    // let mongo_id = mongodb::bson::Uuid::from_uuid(doc_id); // BSON binary subtype 4
-
-    let doc_id = Uuid::new_v4();
-    let doc_id_str = doc_id.to_string(); 
-
-    if let Err(e) = docs_coll.insert_one(
-        doc! {
-            "_id":        &doc_id_str,   // store as string
-            "filename":   filename,
-            "root_hash":  &root.hash,
-            "n_pages":    pages.len() as i32,
-            "created_at": bson::DateTime::now(),
-        },
-        None,
-    ).await {
-        eprintln!("insert document error: {e}");
-        return HttpResponse::InternalServerError().body("db error");
-    }
-
-    // bulk insert page hashes
-    let page_docs: Vec<_> = page_hashes
-    .iter()
-    .enumerate()
-    .map(|(idx, h)| {
-        doc! {
-            "document_id": &doc_id_str,
+/// The options a stored document was extracted with, so the same file
+/// extracts to the same leaves again. Settings a document predates fall
+/// back to what was then the only behaviour.
+pub(crate) fn recorded_options(doc: &Document) -> extractor::Options {
+    let doc = doc.get_document("member_options").unwrap_or(doc);
+    extractor::Options {
+        mode:       doc.get_str("extract_mode").ok().and_then(Mode::from_id).unwrap_or(Mode::Text),
+        profile:    doc.get_str("canon_profile").ok().and_then(Profile::from_id).unwrap_or(Profile::Raw),
+        chunk_size: doc
+            .get_i64("chunk_size")
+            .ok()
+            .and_then(|n| usize::try_from(n).ok())
+            .unwrap_or(extractor::DEFAULT_CHUNK_SIZE),
+        metadata:   doc.contains_key("metadata_index") || doc.get_bool("metadata").unwrap_or(false),
+        extractor:  doc.get_str("extractor").ok().and_then(extractor::extractor_by_name).map(|e| e.name()),
+    }
+}
+
+// form mode: root of the blank template's pages, comparable with a
+// published template uploaded in content mode
+fn template_root(ex: &extractor::Extracted) -> Option<&str> {
+    if ex.mode != Mode::Form {
+        return None;
+    }
+    ex.members.iter().find(|m| m.path == extractor::TEMPLATE_KEY).map(|m| m.doc.root.as_str())
+}
+
+fn is_archive(ex: &extractor::Extracted) -> bool {
+    ex.unit == Unit::Member
+}
+
+// What an archive's members were extracted with, read back by
+// `recorded_options`; each member's own record says what applied to it.
+fn member_options(opts: &extractor::Options) -> Document {
+    let mut options = doc! {
+        "extract_mode":  opts.mode.id(),
+        "canon_profile": opts.profile.id(),
+        "chunk_size":    opts.chunk_size as i64,
+        "metadata":      opts.metadata,
+    };
+    if let Some(name) = opts.extractor {
+        options.insert("extractor", name);
+    }
+    options
+}
+
+/// Where an archive member sits in its archive.
+struct ParentLink<'a> {
+    id:    &'a str,
+    index: usize,
+    path:  &'a str,
+}
+
+// Flattens an extraction into `documents` / `pages` records. Embedded
+// files (archive members, e-mail attachments) become documents of their
+// own, linked to their container through `parent_id` + `parent_index`;
+// returns a summary of those members.
+fn collect_records(
+    doc_id:   &str,
+    filename: &str,
+    ex:       &extractor::Extracted,
+    opts:     &extractor::Options,
+    parent:   Option<ParentLink>,
+    docs:     &mut Vec<Document>,
+    pages:    &mut Vec<Document>,
+) -> Vec<serde_json::Value> {
+    let mut record = doc! {
+        "_id":          doc_id,   // store as string
+        "filename":     filename,
+        "root_hash":    &ex.root,
+        "n_pages":      ex.n_pages() as i32,
+        "n_leaves":     ex.leaves.len() as i32,
+        "format":       ex.format.id(),
+        "unit":         ex.unit.id(),
+        "extractor":    ex.extractor,
+        "extractor_version": ex.extractor_version,
+        "file_size":    ex.size as i64,
+        "created_at":   bson::DateTime::now(),
+    };
+    // an archive's leaves are its members', whatever the mode
+    if is_archive(ex) {
+        record.insert("member_options", member_options(opts));
+    } else {
+        record.insert("extract_mode", ex.mode.id());
+    }
+    // JSON/CSV values are committed verbatim, profiles don't apply
+    if ex.mode == Mode::Text && !matches!(ex.unit, Unit::Field | Unit::Row | Unit::Member) {
+        record.insert("canon_profile", opts.profile.id());
+    }
+    if ex.mode == Mode::Raw {
+        record.insert("chunk_size", opts.chunk_size as i64);
+    }
+    if let Some(idx) = ex.leaves.iter().position(|l| l.source == Source::Metadata) {
+        record.insert("metadata_index", idx as i32);
+    }
+    if let Some(root) = template_root(ex) {
+        record.insert("template_root", root);
+    }
+    if !ex.signatures.is_empty() {
+        let index = ex.leaves.iter().position(|l| l.source == Source::Signatures);
+        record.insert("signatures_index", index.map(|i| i as i32));
+        record.insert("signatures", bson::to_bson(&ex.signatures).unwrap_or_default());
+    }
+    if ex.stamped {
+        record.insert("stamped", true);
+    }
+    if let Some(p) = parent {
+        record.insert("parent_id", p.id);
+        record.insert("parent_index", p.index as i32);
+        record.insert("member_path", p.path);
+    }
+    docs.push(record);
+
+    let member_ids: Vec<String> = ex.members.iter().map(|_| Uuid::new_v4().to_string()).collect();
+
+    for (idx, (h, leaf)) in ex.hashes.iter().zip(&ex.leaves).enumerate() {
+        let mut page = doc! {
+            "document_id": doc_id,
             "page_index":  idx as i32,
-            "page_hash":   h
+            "page_hash":   h,
+            "source":      leaf.source.id(),
+            "status":      leaf.status.id()
+        };
+        if let Some(error) = &leaf.error {
+            page.insert("error", error);
+        }
+        if let Some(label) = ex.labels.get(idx) {
+            page.insert("label", label);
+        }
+        // disclosable leaves keep their preimage for /fields, /rows, …
+        if let Some(key) = &leaf.key {
+            page.insert("key", key);
+            page.insert("preimage", String::from_utf8_lossy(&leaf.payload).into_owned());
+        }
+        if let Some((m, id)) = ex.members.iter().zip(&member_ids).find(|(m, _)| m.index == idx) {
+            page.insert("member_path", &m.path);
+            page.insert("member_id", id);
         }
+        pages.push(page);
+    }
+
+    let mut summary = Vec::with_capacity(ex.members.len());
+    for (member, member_id) in ex.members.iter().zip(&member_ids) {
+        let link = ParentLink { id: doc_id, index: member.index, path: &member.path };
+        collect_records(member_id, &member.path, &member.doc, opts, Some(link), docs, pages);
+        summary.push(member_summary(member, member_id));
+    }
+    summary
+}
+
+fn member_summary(member: &extractor::Member, member_id: &str) -> serde_json::Value {
+    serde_json::json!({
+        "path":        member.path,
+        "document_id": member_id,
+        "root_hash":   member.doc.root,
+        "n_pages":     member.doc.n_pages(),
+        "n_leaves":    member.doc.leaves.len(),
+        "format":      member.doc.format.id(),
+        "extract_mode": member.doc.mode.id(),
+        "extractor":   member.doc.extractor,
+        "report":      leaf_report(&member.doc)
     })
-    .collect();
-    if let Err(e) = pages_coll.insert_many(page_docs, None).await {
-        eprintln!("insert pages error: {e}");
-        return HttpResponse::InternalServerError().body("db error");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicates_keep_the_original_longest() {
+        let (early, late) = (bson::DateTime::from_millis(1_000), bson::DateTime::from_millis(2_000));
+        let kept = |until: Option<bson::DateTime>| match until {
+            Some(t) => doc! { "blob_store": "fs", "retain_until": t },
+            None    => doc! { "blob_store": "fs" },
+        };
+        assert_eq!(kept_until(&kept(Some(early)), Some(late)), Some(late));
+        assert_eq!(kept_until(&kept(Some(late)), Some(early)), Some(late));
+        // for good on either side wins
+        assert_eq!(kept_until(&kept(None), Some(late)), None);
+        assert_eq!(kept_until(&kept(Some(early)), None), None);
+        // an expiry left behind once the sweep dropped the original
+        assert_eq!(kept_until(&doc! { "retain_until": late }, Some(early)), Some(early));
     }
+    #[test]
+    fn archives_record_what_their_members_used() {
+        let opts = extractor::Options {
+            mode:       Mode::Content,
+            profile:    Profile::Raw,
+            chunk_size: 1024,
+            metadata:   true,
+            extractor:  Some("pdf-extract"),
+        };
+        let record = doc! { "unit": "member", "extractor": "archive", "member_options": member_options(&opts) };
+        let again = recorded_options(&record);
+        assert_eq!(again.mode, opts.mode);
+        assert_eq!(again.profile.id(), opts.profile.id());
+        assert_eq!(again.chunk_size, opts.chunk_size);
+        assert!(again.metadata);
+        assert_eq!(again.extractor, opts.extractor);
+    }
+    #[test]
+    fn metadata_leaf_is_not_a_page() {
+        use lopdf::{Dictionary, Object, Stream};
+        let mut pdf = lopdf::Document::with_version("1.7");
+        let pages_id = pdf.new_object_id();
+        let kids: Vec<Object> = (0..2)
+            .map(|i| {
+                let content = pdf.add_object(Stream::new(Dictionary::new(), format!("{i} 0 10 10 re f").into_bytes()));
+                Object::Reference(pdf.add_object(Dictionary::from_iter([
+                    ("Type", Object::Name(b"Page".to_vec())),
+                    ("Parent", Object::Reference(pages_id)),
+                    ("Contents", Object::Reference(content)),
+                ])))
+            })
+            .collect();
+        pdf.objects.insert(pages_id, Object::Dictionary(Dictionary::from_iter([
+            ("Type", Object::Name(b"Pages".to_vec())),
+            ("Count", Object::Integer(2)),
+            ("MediaBox", Object::Array(vec![0.into(), 0.into(), 612.into(), 792.into()])),
+            ("Kids", Object::Array(kids)),
+        ])));
+        let catalog = pdf.add_object(Dictionary::from_iter([
+            ("Type", Object::Name(b"Catalog".to_vec())),
+            ("Pages", Object::Reference(pages_id)),
+        ]));
+        pdf.trailer.set("Root", Object::Reference(catalog));
+        let mut bytes = Vec::new();
+        pdf.save_to(&mut bytes).unwrap();
 
-    // 5) respond
-    HttpResponse::Ok().json(serde_json::json!({
-        "document_id": doc_id.to_string(),
-        "root_hash":   root.hash,
-        "n_pages":     pages.len()
-    }))
+        let opts = extractor::Options {
+            mode:       Mode::Content,
+            profile:    Profile::Raw,
+            chunk_size: 1024,
+            metadata:   true,
+            extractor:  None,
+        };
+        let ex = extractor::extract(&bytes, "two.pdf", &opts).unwrap();
+        let (mut docs, mut pages) = (Vec::new(), Vec::new());
+        collect_records("doc", "two.pdf", &ex, &opts, None, &mut docs, &mut pages);
+        assert_eq!(docs[0].get_i32("n_pages"), Ok(2));
+        assert_eq!(docs[0].get_i32("n_leaves"), Ok(3));
+        assert_eq!(docs[0].get_i32("metadata_index"), Ok(2));
+        assert_eq!(crate::handlers::proof::n_leaves(&docs[0]), 3);
+        // records from before `n_leaves` counted every leaf as a page
+        assert_eq!(crate::handlers::proof::n_leaves(&doc! { "n_pages": 3 }), 3);
+    }
 }
@@ -0,0 +1,154 @@
+use actix_multipart::Multipart;
+use actix_web::{web, HttpResponse, Responder};
+use futures_util::StreamExt;
+use mongodb::{bson::{doc, Document}, options::FindOptions, Database};
+use serde_json::json;
+use std::collections::BTreeMap;
+use crate::handlers::documents::{decrypt_upload, intake, read_upload, UploadOptions};
+use crate::handlers::proof::{load_page_hashes, n_leaves};
+use crate::merkle::coreFunctions::{proof_from_levels, tree_levels};
+use crate::utils::extractor;
+
+/// stored leaves returned per lookup; past it the response is `truncated`
+const MAX_MATCHES: i64 = 1000;
+
+pub fn register(cfg: &mut web::ServiceConfig) {
+    cfg.route("/pages/{hash}", web::get().to(page_route));
+    cfg.route("/lookup", web::post().to(lookup_route));
+}
+
+/// Every stored document with a leaf of this hash, with its index and
+/// proof against the document's root.
+async fn page_route(path: web::Path<String>, db: web::Data<Database>) -> impl Responder {
+    let hash = path.into_inner().to_ascii_lowercase();
+    if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return HttpResponse::BadRequest().body("not a SHA-256 hex digest");
+    }
+    match containing(&db, std::slice::from_ref(&hash)).await {
+        Ok((documents, truncated)) => HttpResponse::Ok().json(json!({
+            "documents": documents,
+            "page_hash": hash,
+            "truncated": truncated,
+        })),
+        Err(e) => {
+            eprintln!("lookup error: {e}");
+            HttpResponse::InternalServerError().body("db error")
+        }
+    }
+}
+
+/// Upload loose pages (multipart `file`, plus `password`) → the stored
+/// documents containing any of them. The file is hashed as
+/// `POST /documents` would with the same query options (`mode`,
+/// `profile`, `extractor`, …), so it only finds documents uploaded alike.
+async fn lookup_route(
+    mut payload: Multipart,
+    opts:        web::Query<UploadOptions>,
+    db:          web::Data<Database>,
+) -> impl Responder {
+    let opts = match intake(&opts) {
+        Ok(i)  => i.opts,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let upload = match read_upload(&mut payload).await {
+        Ok(u)     => u,
+        Err(resp) => return resp,
+    };
+    let decrypted = match decrypt_upload(&upload, opts.mode) {
+        Ok(plain) => plain,
+        Err(e)    => return HttpResponse::BadRequest().body(format!("extract error: {e:#}")),
+    };
+    let bytes: &[u8] = decrypted.as_deref().unwrap_or(&upload.bytes);
+    let extracted = match extractor::extract(bytes, &upload.detect_name(), &opts) {
+        Ok(x)  => x,
+        Err(e) => return HttpResponse::BadRequest().body(format!("extract error: {e:#}")),
+    };
+
+    let (documents, truncated) = match containing(&db, &extracted.hashes).await {
+        Ok(d)  => d,
+        Err(e) => {
+            eprintln!("lookup error: {e}");
+            return HttpResponse::InternalServerError().body("db error");
+        }
+    };
+    let pages: Vec<_> = extracted
+        .hashes
+        .iter()
+        .enumerate()
+        .map(|(index, hash)| {
+            let found = documents
+                .iter()
+                .filter(|d| d["pages"].as_array().is_some_and(|p| p.iter().any(|p| p["page_hash"] == **hash)))
+                .count();
+            json!({ "documents": found, "hash": hash, "index": index })
+        })
+        .collect();
+    HttpResponse::Ok().json(json!({
+        "documents":    documents,
+        "extract_mode": opts.mode.id(),
+        "pages":        pages,
+        "truncated":    truncated,
+    }))
+}
+
+// Stored leaves with any of these hashes, grouped by document, each with
+// its proof; `true` when there were more than `MAX_MATCHES`.
+async fn containing(db: &Database, hashes: &[String]) -> mongodb::error::Result<(Vec<serde_json::Value>, bool)> {
+    // one more than returned, to tell whether any were left out; sorted
+    // so the same matches are kept whichever order Mongo returns them in
+    let options = FindOptions::builder()
+        .sort(doc! { "document_id": 1, "page_index": 1 })
+        .limit(MAX_MATCHES + 1)
+        .build();
+    let mut cursor = db
+        .collection::<Document>("pages")
+        .find(doc! { "page_hash": { "$in": hashes } }, options)
+        .await?;
+    let mut found: BTreeMap<String, Vec<Document>> = BTreeMap::new();
+    let mut matches = 0;
+    let mut truncated = false;
+    while let Some(page) = cursor.next().await {
+        let page = page?;
+        matches += 1;
+        if matches > MAX_MATCHES {
+            truncated = true;
+            break;
+        }
+        if let Ok(id) = page.get_str("document_id") {
+            found.entry(id.to_owned()).or_default().push(page);
+        }
+    }
+
+    let docs = db.collection::<Document>("documents");
+    let mut documents = Vec::with_capacity(found.len());
+    for (doc_id, pages) in found {
+        let Some(meta) = docs.find_one(doc! { "_id": &doc_id }, None).await? else { continue };
+        let n_leaves = n_leaves(&meta);
+        let levels = tree_levels(&load_page_hashes(db, &doc_id, n_leaves).await?);
+
+        let pages: Vec<_> = pages
+            .iter()
+            .filter_map(|p| {
+                let index = usize::try_from(p.get_i32("page_index").ok()?).ok().filter(|&i| i < n_leaves)?;
+                let mut entry = json!({
+                    "page_hash":  p.get_str("page_hash").unwrap_or_default(),
+                    "page_index": index,
+                    "proof":      proof_from_levels(&levels, index),
+                });
+                if let Ok(label) = p.get_str("label") {
+                    entry["label"] = label.into();
+                }
+                Some(entry)
+            })
+            .collect();
+        documents.push(json!({
+            "created_at":  meta.get_datetime("created_at").ok().map(|t| t.to_string()),
+            "document_id": doc_id,
+            "filename":    meta.get_str("filename").ok(),
+            "pages":       pages,
+            "parent_id":   meta.get_str("parent_id").ok(),
+            "root_hash":   meta.get_str("root_hash").ok(),
+        }));
+    }
+    Ok((documents, truncated))
+}
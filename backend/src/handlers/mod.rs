@@ -1,3 +1,11 @@
+pub mod diff;
+pub mod disclose;
 pub mod documents;
+pub mod lookup;
+pub mod pages;
 pub mod proof;
+pub mod roots;
+pub mod stamp;
+pub mod uploads;
 pub mod verify;
+pub mod verify_file;
@@ -0,0 +1,138 @@
+use actix_multipart::Multipart;
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{bson::{doc, Document}, Database};
+use crate::db::blob::Blobs;
+use crate::handlers::documents::{canonical_id, read_upload, stored_original};
+use crate::handlers::proof::n_leaves;
+use crate::merkle::coreFunctions::generate_hash;
+use crate::utils::extractor::{self, Mode};
+
+pub fn register(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/documents/{id}/pages/{page}/pdf")
+            .route(web::get().to(stored_page_pdf_route))
+            .route(web::post().to(page_pdf_route)),
+    );
+}
+
+/// Re-upload the original of a `mode=page-pdf` document (multipart, plus
+/// `password` if it is encrypted) → page `{page}` as a standalone one-page
+/// PDF. Served only when it hashes to the stored leaf, so the file and
+/// `/documents/{id}/proof/{page}` go together.
+async fn page_pdf_route(
+    path:        web::Path<(String, usize)>,
+    mut payload: Multipart,
+    db:          web::Data<Database>,
+) -> impl Responder {
+    let (doc_id, page_idx) = path.into_inner();
+    let doc_id = match canonical_id(&db, doc_id).await {
+        Ok(id)    => id,
+        Err(resp) => return resp,
+    };
+    let leaf = match stored_leaf(&db, &doc_id, page_idx).await {
+        Ok(l)     => l,
+        Err(resp) => return resp,
+    };
+    let upload = match read_upload(&mut payload).await {
+        Ok(u)     => u,
+        Err(resp) => return resp,
+    };
+    page_pdf(&upload.bytes, upload.password.as_deref(), page_idx, &leaf)
+}
+
+/// Same, from the original kept in the blob store.
+async fn stored_page_pdf_route(
+    path:  web::Path<(String, usize)>,
+    db:    web::Data<Database>,
+    blobs: web::Data<Blobs>,
+) -> impl Responder {
+    let (doc_id, page_idx) = path.into_inner();
+    let doc_id = match canonical_id(&db, doc_id).await {
+        Ok(id)    => id,
+        Err(resp) => return resp,
+    };
+    let leaf = match stored_leaf(&db, &doc_id, page_idx).await {
+        Ok(l)     => l,
+        Err(resp) => return resp,
+    };
+    let bytes = match stored_original(&blobs, &leaf.doc_meta).await {
+        Ok(b)     => b,
+        Err(resp) => return resp,
+    };
+    page_pdf(&bytes, None, page_idx, &leaf)
+}
+
+struct StoredLeaf {
+    doc_meta:  Document,
+    page_hash: String,
+    label:     String,
+}
+
+async fn stored_leaf(db: &Database, doc_id: &str, page_idx: usize) -> Result<StoredLeaf, HttpResponse> {
+    // ── 1. document metadata ─────────────────────────────────────────────
+    let doc_meta = match db
+        .collection::<Document>("documents")
+        .find_one(doc! { "_id": doc_id }, None)
+        .await
+    {
+        Ok(Some(d)) => d,
+        Ok(None)    => return Err(HttpResponse::NotFound().body("document not found")),
+        Err(e)      => {
+            eprintln!("find document error: {e}");
+            return Err(HttpResponse::InternalServerError().body("db error"));
+        }
+    };
+    if doc_meta.get_str("extract_mode").ok() != Some(Mode::PagePdf.id()) {
+        return Err(HttpResponse::BadRequest()
+            .body(format!("document was not uploaded with mode={}", Mode::PagePdf.id())));
+    }
+
+    // ── 2. the stored leaf ───────────────────────────────────────────────
+    let page = match db
+        .collection::<Document>("pages")
+        .find_one(doc! { "document_id": doc_id, "page_index": page_idx as i32 }, None)
+        .await
+    {
+        Ok(Some(p)) => p,
+        Ok(None)    => {
+            return Err(HttpResponse::BadRequest()
+                .body(format!("page index out of range (0‥{})", n_leaves(&doc_meta).saturating_sub(1))));
+        }
+        Err(e) => {
+            eprintln!("find page error: {e}");
+            return Err(HttpResponse::InternalServerError().body("db error"));
+        }
+    };
+    Ok(StoredLeaf {
+        page_hash: page.get_str("page_hash").unwrap_or_default().to_owned(),
+        label:     page.get_str("label").unwrap_or_default().to_owned(),
+        doc_meta,
+    })
+}
+
+fn page_pdf(bytes: &[u8], password: Option<&str>, page_idx: usize, leaf: &StoredLeaf) -> HttpResponse {
+    // ── 3. rebuild the page from the original ────────────────────────────
+    let decrypted = match extractor::decrypt_pdf(bytes, password) {
+        Ok(plain) => plain,
+        Err(e)    => return HttpResponse::BadRequest().body(format!("extract error: {e:#}")),
+    };
+    let file = match extractor::pdf_single_page(decrypted.as_deref().unwrap_or(bytes), page_idx) {
+        Ok(f)  => f,
+        Err(e) => return HttpResponse::BadRequest().body(format!("extract error: {e:#}")),
+    };
+
+    // ── 4. only the page the root commits to ─────────────────────────────
+    if generate_hash(&file) != leaf.page_hash {
+        return HttpResponse::Conflict()
+            .body("uploaded file does not produce this page's leaf; is it the original?");
+    }
+    let name = match leaf.label.as_str() {
+        ""    => format!("page-{page_idx}.pdf"),
+        label => format!("page-{label}.pdf"),
+    };
+    HttpResponse::Ok()
+        .content_type("application/pdf")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", name.replace('"', ""))))
+        .insert_header(("X-Page-Hash", leaf.page_hash.as_str()))
+        .body(file)
+}
@@ -1,7 +1,11 @@
 use actix_web::{web, HttpResponse, Responder};
 use futures_util::StreamExt;
 use mongodb::{bson::{doc, Document}, Database};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use crate::handlers::documents::canonical_id;
+use crate::merkle::coreFunctions::{generate_hash, generate_proof_for_index, proof_from_levels, tree_levels};
+use crate::utils::canonical::Profile;
+use crate::utils::extractor::{member_leaf_payload, Mode};
 
 #[derive(Serialize)]
 struct ProofResponse {
@@ -9,6 +13,60 @@ struct ProofResponse {
     root_hash:  String,
     proof:      Vec<(String, String)>,  // bottom‑up sibling list
     page_index: usize,
+    extract_mode: String,               // what the leaf was computed from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    canon_profile: Option<String>,      // text profile (text mode only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extractor: Option<String>,          // engine name@version that made the leaf
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_label: Option<String>,         // printed label, e.g. "iv" (PDFs)
+}
+
+#[derive(Deserialize)]
+struct PageQuery {
+    by: Option<String>,   // "label" to read a numeric {page} as a label
+}
+
+#[derive(Deserialize)]
+struct RangeQuery {
+    start: u64,   // inclusive byte offset
+    end:   u64,   // exclusive byte offset
+}
+
+#[derive(Serialize)]
+struct ChunkProof {
+    chunk_index: usize,
+    page_hash:   String,
+    proof:       Vec<(String, String)>,
+}
+
+#[derive(Serialize)]
+struct RangeProofResponse {
+    root_hash:  String,
+    chunk_size: u64,
+    start:      u64,
+    end:        u64,
+    chunks:     Vec<ChunkProof>,   // one per chunk in [start, end)
+}
+
+#[derive(Serialize)]
+struct ChainStep {
+    document_id: String,
+    root_hash:   String,
+    leaf_index:  usize,
+    leaf_hash:   String,
+    proof:       Vec<(String, String)>,
+    // set when the leaf is an archive member: leaf = H(member-v1 ‖ path ‖ previous root)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    member_path: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ChainProofResponse {
+    page_hash:  String,
+    page_index: usize,
+    root_hash:  String,           // outermost root, the one to anchor
+    steps:      Vec<ChainStep>,   // page → file → archive …
 }
 
 pub fn register(cfg: &mut web::ServiceConfig) {
@@ -16,13 +74,62 @@ pub fn register(cfg: &mut web::ServiceConfig) {
         "/documents/{id}/proof/{page}",
         web::get().to(generate_proof_route),
     );
+    cfg.route(
+        "/documents/{id}/proof/{page}/chain",
+        web::get().to(chain_proof_route),
+    );
+    cfg.route(
+        "/documents/{id}/range-proof",
+        web::get().to(range_proof_route),
+    );
+}
+
+/// Leaves in a document's tree: its pages plus any metadata and
+/// signatures leaves. Records stored before `n_leaves` counted every leaf
+/// in `n_pages`.
+pub(crate) fn n_leaves(doc_meta: &Document) -> usize {
+    doc_meta
+        .get_i32("n_leaves")
+        .or_else(|_| doc_meta.get_i32("n_pages"))
+        .ok()
+        .and_then(|n| usize::try_from(n).ok())
+        .unwrap_or(0)
+}
+
+/// Every page_hash of a document, in leaf order.
+pub(crate) async fn load_page_hashes(
+    db:       &Database,
+    doc_id:   &str,
+    n_leaves: usize,
+) -> mongodb::error::Result<Vec<String>> {
+    let pages_coll = db.collection::<Document>("pages");
+    let mut cursor = pages_coll
+        .find(doc! { "document_id": doc_id }, None)
+        .await?;
+
+    let mut page_hashes = vec![String::new(); n_leaves];
+    while let Some(Ok(p)) = cursor.next().await {
+        let idx = p.get_i32("page_index").unwrap_or(-1);
+        if let Some(slot) = usize::try_from(idx).ok().and_then(|i| page_hashes.get_mut(i)) {
+            *slot = p.get_str("page_hash").unwrap_or_default().to_owned();
+        }
+    }
+    Ok(page_hashes)
 }
 
+/// `{page}` is the 0-based leaf index, or a printed page label such as
+/// `iv` or `A-3`; `?by=label` reads a numeric one as a label too.
 async fn generate_proof_route(
-    path: web::Path<(String, usize)>,
-    db:   web::Data<Database>,
+    path:  web::Path<(String, String)>,
+    query: web::Query<PageQuery>,
+    db:    web::Data<Database>,
 ) -> impl Responder {
-    let (doc_id, page_idx) = path.into_inner();
+    let (doc_id, page) = path.into_inner();
+    let doc_id = match canonical_id(&db, doc_id).await {
+        Ok(id)    => id,
+        Err(resp) => return resp,
+    };
+    let by_label = query.by.as_deref() == Some("label");
 
     // ── 1. document metadata ─────────────────────────────────────────────
     let docs_coll = db.collection::<Document>("documents");
@@ -36,34 +143,55 @@ async fn generate_proof_route(
     };
 
     let root_hash = doc_meta.get_str("root_hash").unwrap_or_default().to_owned();
-    let n_pages   = doc_meta.get_i32("n_pages").unwrap_or(0);
-    if page_idx as i32 >= n_pages {
+    let n_leaves  = n_leaves(&doc_meta);
+    // documents stored before modes/profiles existed were raw text; an
+    // archive's leaves are its members
+    let extract_mode = match doc_meta.get_str("unit") {
+        Ok("member") => "member",
+        _            => doc_meta.get_str("extract_mode").unwrap_or(Mode::Text.id()),
+    }
+    .to_owned();
+    let canon_profile = (extract_mode == Mode::Text.id()).then(|| {
+        doc_meta
+            .get_str("canon_profile")
+            .unwrap_or(Profile::Raw.id())
+            .to_owned()
+    });
+    // documents stored before the registry have no engine recorded
+    let extractor = doc_meta.get_str("extractor").ok().map(|name| {
+        format!("{name}@{}", doc_meta.get_str("extractor_version").unwrap_or_default())
+    });
+    let (page_idx, page_label) = match page.parse::<usize>() {
+        Ok(idx) if !by_label => match page_label(&db, &doc_id, idx).await {
+            Ok(label) => (idx, label),
+            Err(e)    => {
+                eprintln!("find page error: {e}");
+                return HttpResponse::InternalServerError().body("db error");
+            }
+        },
+        _ => match page_by_label(&db, &doc_id, &page).await {
+            Ok(idx) => (idx, Some(page)),
+            Err(resp) => return resp,
+        },
+    };
+    if page_idx >= n_leaves {
         return HttpResponse::BadRequest()
-            .body(format!("page index out of range (0‥{})", n_pages - 1));
+            .body(format!("leaf index out of range (0‥{})", n_leaves.saturating_sub(1)));
     }
 
     // ── 2. load every page_hash in its slot ──────────────────────────────
-    let pages_coll = db.collection::<Document>("pages");
-    let mut cursor = pages_coll
-        .find(doc! { "document_id": &doc_id }, None)
-        .await
-        .unwrap();
-
-    let mut page_hashes = vec![String::new(); n_pages as usize];
-    while let Some(Ok(p)) = cursor.next().await {
-        let idx = p.get_i32("page_index").unwrap() as usize;
-        page_hashes[idx] = p.get_str("page_hash").unwrap().to_owned();
-    }
-    let target_single = &page_hashes.clone()[page_idx];
-
-    // ── 3. single‑hash tree (same as upload) + proof ─────────────────────
-    let root_node =
-        crate::merkle::coreFunctions::build_tree_from_hashes(page_hashes);
-
-    let mut proof =
-        crate::merkle::coreFunctions::generate_proof(&root_node, target_single);
+    let page_hashes = match load_page_hashes(&db, &doc_id, n_leaves).await {
+        Ok(h)  => h,
+        Err(e) => {
+            eprintln!("load pages error: {e}");
+            return HttpResponse::InternalServerError().body("db error");
+        }
+    };
+    let target_single = &page_hashes[page_idx];
 
-    
+    // ── 3. proof by position: identical pages (blank, repeated) share a hash
+    let proof =
+        crate::merkle::coreFunctions::generate_proof_for_index(&page_hashes, page_idx);
 
     // ── 4. respond ───────────────────────────────────────────────────────
     HttpResponse::Ok().json(ProofResponse {
@@ -71,5 +199,192 @@ async fn generate_proof_route(
         root_hash,
         proof,
         page_index: page_idx,
+        extract_mode,
+        canon_profile,
+        extractor,
+        page_label,
+    })
+}
+
+async fn page_label(db: &Database, doc_id: &str, idx: usize) -> mongodb::error::Result<Option<String>> {
+    let page = db
+        .collection::<Document>("pages")
+        .find_one(doc! { "document_id": doc_id, "page_index": idx as i32 }, None)
+        .await?;
+    Ok(page.and_then(|p| p.get_str("label").ok().map(str::to_owned)))
+}
+
+// labels needn't be unique (two sections both numbered from 1)
+async fn page_by_label(db: &Database, doc_id: &str, label: &str) -> Result<usize, HttpResponse> {
+    let mut cursor = match db
+        .collection::<Document>("pages")
+        .find(doc! { "document_id": doc_id, "label": label }, None)
+        .await
+    {
+        Ok(c)  => c,
+        Err(e) => {
+            eprintln!("find page error: {e}");
+            return Err(HttpResponse::InternalServerError().body("db error"));
+        }
+    };
+    let mut indices = Vec::new();
+    while let Some(Ok(p)) = cursor.next().await {
+        indices.push(p.get_i32("page_index").unwrap_or(0) as usize);
+    }
+    indices.sort_unstable();
+    match indices.as_slice() {
+        []    => Err(HttpResponse::NotFound().body(format!("no page labelled {label:?}"))),
+        [idx] => Ok(*idx),
+        _     => Err(HttpResponse::BadRequest()
+            .body(format!("label {label:?} is ambiguous, pages {indices:?}; use the index"))),
+    }
+}
+
+/// Chunk-aligned byte range of a raw-mode document → proofs for every chunk
+/// it covers. `end` may be the file size when the last chunk is short.
+async fn range_proof_route(
+    path:  web::Path<String>,
+    query: web::Query<RangeQuery>,
+    db:    web::Data<Database>,
+) -> impl Responder {
+    let doc_id = path.into_inner();
+    let doc_id = match canonical_id(&db, doc_id).await {
+        Ok(id)    => id,
+        Err(resp) => return resp,
+    };
+    let RangeQuery { start, end } = query.into_inner();
+
+    let docs_coll = db.collection::<Document>("documents");
+    let doc_meta = match docs_coll.find_one(doc! { "_id": &doc_id }, None).await {
+        Ok(Some(d)) => d,
+        Ok(None)    => return HttpResponse::NotFound().body("document not found"),
+        Err(e)      => {
+            eprintln!("find document error: {e}");
+            return HttpResponse::InternalServerError().body("db error");
+        }
+    };
+
+    let (chunk_size, file_size) = match (doc_meta.get_i64("chunk_size"), doc_meta.get_i64("file_size")) {
+        (Ok(c), Ok(f)) => (c as u64, f as u64),
+        _ => return HttpResponse::BadRequest().body("document was not uploaded in raw mode"),
+    };
+    if start >= end || end > file_size {
+        return HttpResponse::BadRequest()
+            .body(format!("range must satisfy start < end <= {file_size}"));
+    }
+    if start % chunk_size != 0 || (end % chunk_size != 0 && end != file_size) {
+        return HttpResponse::BadRequest()
+            .body(format!("range must be aligned to chunk_size {chunk_size}"));
+    }
+
+    let page_hashes = match load_page_hashes(&db, &doc_id, n_leaves(&doc_meta)).await {
+        Ok(h)  => h,
+        Err(e) => {
+            eprintln!("load pages error: {e}");
+            return HttpResponse::InternalServerError().body("db error");
+        }
+    };
+
+    let first = (start / chunk_size) as usize;
+    let last  = end.div_ceil(chunk_size) as usize;
+    let levels = tree_levels(&page_hashes);
+    let chunks = (first..last)
+        .map(|i| ChunkProof {
+            chunk_index: i,
+            page_hash:   page_hashes[i].clone(),
+            proof:       proof_from_levels(&levels, i),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(RangeProofResponse {
+        root_hash: doc_meta.get_str("root_hash").unwrap_or_default().to_owned(),
+        chunk_size,
+        start,
+        end,
+        chunks,
+    })
+}
+
+/// Page proof for an archive member, continued through every enclosing
+/// archive up to the root that covers the whole bundle. Past `MAX_DEPTH`
+/// enclosing documents there is no such root to return: an error.
+async fn chain_proof_route(
+    path: web::Path<(String, usize)>,
+    db:   web::Data<Database>,
+) -> impl Responder {
+    const MAX_DEPTH: usize = 8;
+    let (doc_id, page_idx) = path.into_inner();
+    let doc_id = match canonical_id(&db, doc_id).await {
+        Ok(id)    => id,
+        Err(resp) => return resp,
+    };
+    let docs_coll = db.collection::<Document>("documents");
+
+    let mut current     = doc_id;
+    let mut leaf_index  = page_idx;
+    let mut member_path: Option<String> = None;
+    let mut steps: Vec<ChainStep> = Vec::new();
+
+    loop {
+        let doc_meta = match docs_coll.find_one(doc! { "_id": &current }, None).await {
+            Ok(Some(d)) => d,
+            Ok(None)    => return HttpResponse::NotFound().body(format!("document {current} not found")),
+            Err(e)      => {
+                eprintln!("find document error: {e}");
+                return HttpResponse::InternalServerError().body("db error");
+            }
+        };
+        let n_leaves = n_leaves(&doc_meta);
+        if leaf_index >= n_leaves {
+            return HttpResponse::BadRequest()
+                .body(format!("leaf index out of range (0‥{})", n_leaves.saturating_sub(1)));
+        }
+        let page_hashes = match load_page_hashes(&db, &current, n_leaves).await {
+            Ok(h)  => h,
+            Err(e) => {
+                eprintln!("load pages error: {e}");
+                return HttpResponse::InternalServerError().body("db error");
+            }
+        };
+
+        // the member leaf must commit to the root one level down
+        if let (Some(p), Some(prev)) = (&member_path, steps.last()) {
+            let expected = generate_hash(member_leaf_payload(p, &prev.root_hash));
+            if page_hashes[leaf_index] != expected {
+                eprintln!("member leaf mismatch: {current}[{leaf_index}]");
+                return HttpResponse::InternalServerError().body("stored archive is inconsistent");
+            }
+        }
+
+        steps.push(ChainStep {
+            document_id: current.clone(),
+            root_hash:   doc_meta.get_str("root_hash").unwrap_or_default().to_owned(),
+            leaf_index,
+            leaf_hash:   page_hashes[leaf_index].clone(),
+            proof:       generate_proof_for_index(&page_hashes, leaf_index),
+            member_path: member_path.take(),
+        });
+
+        match (doc_meta.get_str("parent_id"), doc_meta.get_i32("parent_index")) {
+            (Ok(_), Ok(_)) if steps.len() == MAX_DEPTH => {
+                eprintln!("chain proof: {} is nested more than {MAX_DEPTH} levels deep", steps[0].document_id);
+                return HttpResponse::InternalServerError()
+                    .body(format!("document is nested more than {MAX_DEPTH} levels deep; no proof to the top-level root"));
+            }
+            (Ok(parent), Ok(idx)) => {
+                member_path = Some(doc_meta.get_str("member_path").unwrap_or_default().to_owned());
+                leaf_index  = idx as usize;
+                current     = parent.to_owned();
+            }
+            _ => break,
+        }
+    }
+
+    let first = &steps[0];
+    HttpResponse::Ok().json(ChainProofResponse {
+        page_hash:  first.leaf_hash.clone(),
+        page_index: page_idx,
+        root_hash:  steps.last().unwrap().root_hash.clone(),
+        steps,
     })
 }
@@ -0,0 +1,269 @@
+use actix_web::{web, HttpResponse, Responder};
+use mongodb::{bson::{doc, Document}, Database};
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::merkle::coreFunctions::build_tree_from_hashes;
+use crate::utils::canonical::Profile;
+use crate::utils::extractor::{self, Format, Mode, PageExtractor, Source, Status, Unit};
+
+/// leaves per submission; the body is sized to match
+const MAX_HASHES: usize = 100_000;
+
+pub fn register(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/roots")
+            .app_data(web::JsonConfig::default().limit(MAX_HASHES * 70 + 4096))
+            .route(web::post().to(submit_route)),
+    );
+}
+
+#[derive(Deserialize)]
+struct RootRequest {
+    /// leaf hashes in page order, hex
+    hashes:     Vec<String>,
+    /// how each leaf was hashed; `sha256`, as `POST /documents` does
+    algorithm:  String,
+    /// how leaves were derived: the `mode` the client ran — `text`,
+    /// `content`, `page-pdf` or `raw`
+    scheme:     String,
+    /// file format the leaves came from (`pdf`, `docx`, …); left unset
+    /// when not given, except for the PDF-only schemes
+    format:     Option<String>,
+    /// text: canonicalization profile (default as for uploads)
+    profile:    Option<String>,
+    /// text: engine name, see `utils::extractor::EXTRACTORS`
+    extractor:  Option<String>,
+    /// raw: bytes per leaf
+    chunk_size: Option<usize>,
+    /// raw: length of the file, so `/range` can map bytes to leaves
+    file_size:  Option<u64>,
+    filename:   Option<String>,
+}
+
+/// A checked submission: the leaves and how they were derived.
+struct Submission {
+    hashes:     Vec<String>,
+    mode:       Mode,
+    unit:       Unit,
+    source:     Source,
+    format:     Option<Format>,
+    profile:    Profile,
+    engine:     Option<&'static dyn PageExtractor>,
+    chunk_size: Option<usize>,
+    file_size:  Option<u64>,
+}
+
+fn submission(req: &RootRequest) -> Result<Submission, String> {
+    // ── 1. the leaf scheme ───────────────────────────────────────────────
+    if req.algorithm != "sha256" {
+        return Err(format!("unsupported algorithm: {} (sha256)", req.algorithm));
+    }
+    let (mode, unit, source) = match Mode::from_id(&req.scheme) {
+        Some(Mode::Text)    => (Mode::Text, Unit::Page, Source::Text),
+        Some(Mode::Content) => (Mode::Content, Unit::Page, Source::Content),
+        Some(Mode::PagePdf) => (Mode::PagePdf, Unit::Page, Source::PagePdf),
+        Some(Mode::Raw)     => (Mode::Raw, Unit::Chunk, Source::Chunk),
+        _ => return Err(format!("unsupported scheme: {} (text, content, page-pdf or raw)", req.scheme)),
+    };
+    let format = match req.format.as_deref() {
+        None     => None,
+        Some(id) => match Format::from_id(id) {
+            Some(f) => Some(f),
+            None    => return Err(format!("unknown format: {id}")),
+        },
+    };
+    let format = match (mode, format) {
+        (Mode::Content | Mode::PagePdf, None | Some(Format::Pdf)) => Some(Format::Pdf),
+        (Mode::Content | Mode::PagePdf, Some(f)) => {
+            return Err(format!("{} scheme is only available for PDF, not {}", mode.id(), f.id()));
+        }
+        (Mode::Text, Some(f @ (Format::Zip | Format::Tar | Format::Binary))) => {
+            return Err(format!("text scheme does not apply to {}", f.id()));
+        }
+        (_, f) => f,
+    };
+    let profile = match req.profile.as_deref() {
+        None     => Profile::DEFAULT,
+        Some(id) => match Profile::from_id(id) {
+            Some(p) => p,
+            None    => return Err(format!("unknown profile: {id}")),
+        },
+    };
+    let engine = match req.extractor.as_deref() {
+        None       => None,
+        Some(name) => match extractor::extractor_by_name(name) {
+            Some(e) => Some(e),
+            None    => return Err(format!("unknown extractor: {name}")),
+        },
+    };
+    if let (Some(e), Some(f)) = (engine, format) {
+        if !e.handles(f, mode) {
+            return Err(format!("extractor {} does not handle {} in {} mode", e.name(), f.id(), mode.id()));
+        }
+    }
+    let (chunk_size, file_size) = match (mode, req.chunk_size, req.file_size) {
+        (Mode::Raw, Some(n), Some(len)) if n > 0 && n <= extractor::MAX_CHUNK_SIZE => (Some(n), Some(len)),
+        (Mode::Raw, _, _) => {
+            return Err(format!("raw scheme needs chunk_size 1‥{} and file_size", extractor::MAX_CHUNK_SIZE));
+        }
+        _ => (None, None),
+    };
+
+    // ── 2. the leaves ────────────────────────────────────────────────────
+    if req.hashes.is_empty() || req.hashes.len() > MAX_HASHES {
+        return Err(format!("hashes must hold 1‥{MAX_HASHES} leaves"));
+    }
+    if let (Some(n), Some(len)) = (chunk_size, file_size) {
+        // an empty file still has one (empty) chunk, as uploads do
+        let expected = len.div_ceil(n as u64).max(1);
+        if req.hashes.len() as u64 != expected {
+            return Err(format!("file_size {len} in chunks of {n} needs {expected} hashes, got {}", req.hashes.len()));
+        }
+    }
+    let mut hashes = Vec::with_capacity(req.hashes.len());
+    for (i, h) in req.hashes.iter().enumerate() {
+        if h.len() != 64 || !h.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("hashes[{i}] is not a SHA-256 hex digest"));
+        }
+        // parents hash the hex text, so it must be spelled as ours is
+        hashes.push(h.to_ascii_lowercase());
+    }
+
+    Ok(Submission { hashes, mode, unit, source, format, profile, engine, chunk_size, file_size })
+}
+
+impl Submission {
+    /// The `documents` record, shaped as for an upload.
+    fn record(&self, doc_id: &str, filename: &str, root: &str) -> Document {
+        let mut record = doc! {
+            "_id":            doc_id,
+            "filename":       filename,
+            "root_hash":      root,
+            "n_pages":        self.hashes.len() as i32,
+            "n_leaves":       self.hashes.len() as i32,
+            "unit":           self.unit.id(),
+            "extract_mode":   self.mode.id(),
+            "hash_only":      true,
+            "hash_algorithm": "sha256",
+            "created_at":     bson::DateTime::now(),
+        };
+        if let Some(f) = self.format {
+            record.insert("format", f.id());
+        }
+        if self.mode == Mode::Text {
+            record.insert("canon_profile", self.profile.id());
+        }
+        if let Some(e) = self.engine {
+            record.insert("extractor", e.name());
+            record.insert("extractor_version", e.version());
+        }
+        if let Some(n) = self.chunk_size {
+            record.insert("chunk_size", n as i64);
+        }
+        if let Some(len) = self.file_size {
+            record.insert("file_size", len as i64);
+        }
+        record
+    }
+}
+
+/// Precomputed leaf hashes (JSON) → a document like any upload, for files
+/// that may not leave the customer. Proofs are served as usual; routes
+/// that need the file itself take it from a re-upload.
+async fn submit_route(body: web::Json<RootRequest>, db: web::Data<Database>) -> impl Responder {
+    let req = body.into_inner();
+    let sub = match submission(&req) {
+        Ok(s)  => s,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let root = build_tree_from_hashes(sub.hashes.clone()).hash;
+
+    // ── 3. write to Mongo, as for an upload ──────────────────────────────
+    let doc_id = Uuid::new_v4().to_string();
+    let record = sub.record(&doc_id, req.filename.as_deref().unwrap_or("submitted"), &root);
+    let pages: Vec<Document> = sub.hashes
+        .iter()
+        .enumerate()
+        .map(|(idx, h)| doc! {
+            "document_id": &doc_id,
+            "page_index":  idx as i32,
+            "page_hash":   h,
+            "source":      sub.source.id(),
+            "status":      Status::Ok.id(),
+        })
+        .collect();
+
+    if let Err(e) = db.collection::<Document>("documents").insert_one(record, None).await {
+        eprintln!("insert document error: {e}");
+        return HttpResponse::InternalServerError().body("db error");
+    }
+    if let Err(e) = db.collection::<Document>("pages").insert_many(pages, None).await {
+        eprintln!("insert pages error: {e}");
+        return HttpResponse::InternalServerError().body("db error");
+    }
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "document_id":  doc_id,
+        "root_hash":    root,
+        "n_pages":      sub.hashes.len(),
+        "n_leaves":     sub.hashes.len(),
+        "format":       sub.format.map(Format::id),
+        "unit":         sub.unit.id(),
+        "extract_mode": sub.mode.id(),
+        "extractor":    sub.engine.map(|e| e.name()),
+        "extractor_version": sub.engine.map(|e| e.version()),
+        "hash_only":    true,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(scheme: &str, n: usize) -> RootRequest {
+        RootRequest {
+            hashes:     vec!["AB".repeat(32); n],
+            algorithm:  "sha256".into(),
+            scheme:     scheme.into(),
+            format:     None,
+            profile:    None,
+            extractor:  None,
+            chunk_size: None,
+            file_size:  None,
+            filename:   None,
+        }
+    }
+
+    #[test]
+    fn text_keeps_the_format_it_was_given() {
+        let sub = submission(&request("text", 2)).unwrap();
+        let record = sub.record("d", "f", "r");
+        assert!(record.get("format").is_none());
+        assert_eq!(sub.hashes[0], "ab".repeat(32));
+
+        let req = RootRequest { format: Some("docx".into()), extractor: Some("docx".into()), ..request("text", 2) };
+        let record = submission(&req).unwrap().record("d", "f", "r");
+        assert_eq!(record.get_str("format").unwrap(), "docx");
+        assert_eq!(record.get_str("extractor").unwrap(), "docx");
+        assert_eq!(record.get_str("extractor_version").unwrap(), extractor::extractor_by_name("docx").unwrap().version());
+
+        let req = RootRequest { format: Some("docx".into()), ..request("page-pdf", 2) };
+        assert!(submission(&req).is_err());
+        let req = RootRequest { format: Some("docx".into()), extractor: Some("lopdf".into()), ..request("text", 2) };
+        assert!(submission(&req).is_err());
+    }
+
+    #[test]
+    fn raw_needs_a_consistent_file_size() {
+        let raw = |chunk_size, file_size, n| RootRequest { chunk_size, file_size, ..request("raw", n) };
+        assert!(submission(&raw(Some(4), None, 3)).is_err());
+        assert!(submission(&raw(Some(4), Some(9), 2)).is_err());
+        assert!(submission(&raw(Some(4), Some(9), 4)).is_err());
+        assert!(submission(&raw(Some(4), Some(0), 1)).is_ok());
+
+        let record = submission(&raw(Some(4), Some(9), 3)).unwrap().record("d", "f", "r");
+        assert_eq!(record.get_i64("chunk_size").unwrap(), 4);
+        assert_eq!(record.get_i64("file_size").unwrap(), 9);
+        assert_eq!(record.get_str("unit").unwrap(), "chunk");
+    }
+}
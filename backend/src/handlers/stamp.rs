@@ -0,0 +1,320 @@
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use mongodb::{bson::{doc, Document}, Database};
+use serde_json::json;
+use std::env;
+use crate::db::blob::Blobs;
+use crate::handlers::documents::{canonical_id, read_upload, recorded_options, stored_original};
+use crate::handlers::proof::{load_page_hashes, n_leaves};
+use crate::merkle::coreFunctions::{generate_hash, proof_from_levels, tree_levels};
+use crate::utils::extractor::{self, Format, Mode, Unit};
+use crate::utils::stamp::{stamp_pdf, Stamp};
+
+pub fn register(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/documents/{id}/stamped")
+            .route(web::get().to(stored_stamped_route))
+            .route(web::post().to(stamped_route)),
+    );
+}
+
+/// Re-upload the original PDF (multipart) → a copy carrying its own
+/// verification data: `proofs.json` with the root and every leaf's proof
+/// as an attachment, and a QR code on each page linking to that page's
+/// proof (form mode: its proof chained through the blank template). The
+/// link carries the page's `leaf` hash and proof `digest`, so a printed
+/// page can be checked against proofs.json without this server. The
+/// upload must extract to the stored root, and so does the stamped copy.
+async fn stamped_route(
+    req:         HttpRequest,
+    path:        web::Path<String>,
+    mut payload: Multipart,
+    db:          web::Data<Database>,
+) -> impl Responder {
+    let doc_id = path.into_inner();
+    let doc_id = match canonical_id(&db, doc_id).await {
+        Ok(id)    => id,
+        Err(resp) => return resp,
+    };
+    let doc_meta = match stampable(&db, &doc_id).await {
+        Ok(d)     => d,
+        Err(resp) => return resp,
+    };
+    let upload = match read_upload(&mut payload).await {
+        Ok(u)     => u,
+        Err(resp) => return resp,
+    };
+    stamp(&req, &db, &doc_id, &doc_meta, &upload.bytes).await
+}
+
+/// Same, from the original kept in the blob store.
+async fn stored_stamped_route(
+    req:   HttpRequest,
+    path:  web::Path<String>,
+    db:    web::Data<Database>,
+    blobs: web::Data<Blobs>,
+) -> impl Responder {
+    let doc_id = path.into_inner();
+    let doc_id = match canonical_id(&db, doc_id).await {
+        Ok(id)    => id,
+        Err(resp) => return resp,
+    };
+    let doc_meta = match stampable(&db, &doc_id).await {
+        Ok(d)     => d,
+        Err(resp) => return resp,
+    };
+    let bytes = match stored_original(&blobs, &doc_meta).await {
+        Ok(b)     => b,
+        Err(resp) => return resp,
+    };
+    stamp(&req, &db, &doc_id, &doc_meta, &bytes).await
+}
+
+async fn stampable(db: &Database, doc_id: &str) -> Result<Document, HttpResponse> {
+    // ── 1. document metadata ─────────────────────────────────────────────
+    let doc_meta = match db
+        .collection::<Document>("documents")
+        .find_one(doc! { "_id": doc_id }, None)
+        .await
+    {
+        Ok(Some(d)) => d,
+        Ok(None)    => return Err(HttpResponse::NotFound().body("document not found")),
+        Err(e)      => {
+            eprintln!("find document error: {e}");
+            return Err(HttpResponse::InternalServerError().body("db error"));
+        }
+    };
+    if doc_meta.get_str("format").ok() != Some(Format::Pdf.id()) {
+        return Err(HttpResponse::BadRequest().body("only PDFs can be stamped"));
+    }
+    if doc_meta.get_str("extract_mode").ok() == Some(Mode::Raw.id()) {
+        return Err(HttpResponse::BadRequest().body("raw mode commits to the exact bytes; a stamped copy can't match"));
+    }
+    if doc_meta.get_bool("encrypted").unwrap_or(false) {
+        return Err(HttpResponse::BadRequest().body("encrypted PDFs can't be stamped"));
+    }
+    Ok(doc_meta)
+}
+
+async fn stamp(req: &HttpRequest, db: &Database, doc_id: &str, doc_meta: &Document, bytes: &[u8]) -> HttpResponse {
+    let opts = recorded_options(doc_meta);
+    let root_hash = doc_meta.get_str("root_hash").unwrap_or_default().to_owned();
+
+    // ── 2. the original must extract to the stored root ──────────────────
+    let filename = doc_meta.get_str("filename").unwrap_or("document.pdf").to_owned();
+    let extracted = match extractor::extract(bytes, &filename, &opts) {
+        Ok(x)  => x,
+        Err(e) => return HttpResponse::BadRequest().body(format!("extract error: {e:#}")),
+    };
+    if extracted.root != root_hash {
+        return HttpResponse::Conflict()
+            .body("uploaded file does not extract to this document's root; is it the original?");
+    }
+
+    // ── 3. every leaf's proof ────────────────────────────────────────────
+    let page_hashes = match load_page_hashes(db, doc_id, extracted.leaves.len()).await {
+        Ok(h)  => h,
+        Err(e) => {
+            eprintln!("load pages error: {e}");
+            return HttpResponse::InternalServerError().body("db error");
+        }
+    };
+    let Some(base) = public_url(req) else {
+        eprintln!("stamp: PUBLIC_URL is not set and the request's host is not loopback");
+        return HttpResponse::InternalServerError().body("PUBLIC_URL is not set; the QR codes need a public address");
+    };
+    let leaves = proof_entries(&page_hashes);
+    let proofs = json!({
+        "document_id":  doc_id,
+        "extract_mode": opts.mode.id(),
+        "leaves":       leaves,
+        "root_hash":    root_hash,
+        "verify_url":   format!("{base}/verify"),
+    });
+
+    // ── 4. QR codes: each page's proof ───────────────────────────────────
+    let n_pages = match lopdf::Document::load_mem(bytes) {
+        Ok(pdf) => pdf.get_pages().len(),
+        Err(e)  => return HttpResponse::BadRequest().body(format!("stamp error: {e}")),
+    };
+    // form mode: the pages are leaves of the template member
+    let (page_doc, page_leaves) = match extracted.unit {
+        Unit::Page => (doc_id.to_owned(), leaves),
+        _ => match template(db, doc_id).await {
+            Ok(t)     => t,
+            Err(resp) => return resp,
+        },
+    };
+    let page_codes = (0..n_pages)
+        .map(|i| {
+            let url = match extracted.unit {
+                Unit::Page => format!("{base}/documents/{page_doc}/proof/{i}"),
+                _          => format!("{base}/documents/{page_doc}/proof/{i}/chain"),
+            };
+            page_code(&url, page_leaves.get(i))
+        })
+        .collect();
+    let stamp = Stamp { root_hash: root_hash.clone(), proofs: proofs.to_string().into_bytes(), page_codes };
+    let file = match stamp_pdf(bytes, &stamp) {
+        Ok(f)  => f,
+        Err(e) => return HttpResponse::BadRequest().body(format!("stamp error: {e:#}")),
+    };
+
+    let stem = filename.strip_suffix(".pdf").unwrap_or(&filename).replace('"', "");
+    HttpResponse::Ok()
+        .content_type("application/pdf")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{stem}-stamped.pdf\"")))
+        .insert_header(("X-Root-Hash", root_hash))
+        .body(file)
+}
+
+// The blank template of a form, and the proofs of its pages.
+async fn template(db: &Database, doc_id: &str) -> Result<(String, Vec<serde_json::Value>), HttpResponse> {
+    let filter = doc! { "parent_id": doc_id, "member_path": extractor::TEMPLATE_KEY };
+    let template = match db.collection::<Document>("documents").find_one(filter, None).await {
+        Ok(Some(d)) => d,
+        Ok(None)    => return Err(HttpResponse::InternalServerError().body("form template not stored")),
+        Err(e)      => {
+            eprintln!("find template error: {e}");
+            return Err(HttpResponse::InternalServerError().body("db error"));
+        }
+    };
+    let id = template.get_str("_id").unwrap_or_default().to_owned();
+    match load_page_hashes(db, &id, n_leaves(&template)).await {
+        Ok(hashes) => Ok((id, proof_entries(&hashes))),
+        Err(e)     => {
+            eprintln!("load pages error: {e}");
+            Err(HttpResponse::InternalServerError().body("db error"))
+        }
+    }
+}
+
+// Every leaf with its proof, as listed in proofs.json.
+fn proof_entries(page_hashes: &[String]) -> Vec<serde_json::Value> {
+    let levels = tree_levels(page_hashes);
+    page_hashes
+        .iter()
+        .enumerate()
+        .map(|(index, hash)| {
+            let proof = proof_from_levels(&levels, index);
+            // pins a leaf and its sibling path, to check a proof fetched later
+            let digest = generate_hash(json!([hash, proof]).to_string());
+            json!({
+                "digest":    digest,
+                "index":     index,
+                "leaf_hash": hash,
+                "proof":     proof,
+            })
+        })
+        .collect()
+}
+
+// What a page's QR code encodes: its proof URL, plus the leaf hash and
+// proof digest to look up in proofs.json offline.
+fn page_code(url: &str, entry: Option<&serde_json::Value>) -> String {
+    match entry.and_then(|e| Some((e["leaf_hash"].as_str()?, e["digest"].as_str()?))) {
+        Some((leaf, digest)) => format!("{url}?leaf={leaf}&digest={digest}"),
+        None                 => url.to_owned(),
+    }
+}
+
+// `PUBLIC_URL`; the request's own host only when it is loopback, since
+// whatever goes into a QR code is printed for good
+fn public_url(req: &HttpRequest) -> Option<String> {
+    if let Ok(url) = env::var("PUBLIC_URL") {
+        return Some(url.trim_end_matches('/').to_owned());
+    }
+    let info = req.connection_info();
+    is_loopback(info.host()).then(|| format!("{}://{}", info.scheme(), info.host()))
+}
+
+fn is_loopback(host: &str) -> bool {
+    // `[::1]:8080`, `localhost:8080`, `127.0.0.1`
+    let name = match host.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None     => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name.eq_ignore_ascii_case("localhost")
+        || name.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::Object;
+    use qrcode::{Color, EcLevel, QrCode};
+    use std::collections::HashSet;
+    use crate::utils::extractor::Options;
+    use crate::utils::canonical::Profile;
+
+    // The text a stamped page's QR code encodes: the annotation's
+    // /Contents, once its appearance is checked to draw exactly that code.
+    fn read_code(pdf: &lopdf::Document, page: lopdf::ObjectId) -> String {
+        let page = pdf.get_dictionary(page).unwrap();
+        let annots = page.get(b"Annots").and_then(Object::as_array).unwrap();
+        let annot = pdf.get_dictionary(annots[0].as_reference().unwrap()).unwrap();
+        let text = String::from_utf8(annot.get(b"Contents").and_then(Object::as_str).unwrap().to_vec()).unwrap();
+
+        let ap = annot.get(b"AP").and_then(Object::as_dict).unwrap();
+        let stream = pdf.get_object(ap.get(b"N").and_then(Object::as_reference).unwrap()).and_then(Object::as_stream).unwrap();
+        let side = stream.dict.get(b"BBox").and_then(Object::as_array).unwrap()[2].as_i64().unwrap() as usize;
+        let ops = String::from_utf8(stream.decompressed_content().unwrap()).unwrap();
+        let mut drawn = HashSet::new();
+        for line in ops.lines().filter(|l| l.ends_with(" 1 re")) {
+            let v: Vec<usize> = line.split(' ').take(3).map(|n| n.parse().unwrap()).collect();
+            drawn.extend((v[0]..v[0] + v[2]).map(|x| (x, v[1])));
+        }
+
+        let code = QrCode::with_error_correction_level(&text, EcLevel::L).unwrap();
+        let quiet = (side - code.width()) / 2;
+        let expected: HashSet<_> = code
+            .to_colors()
+            .chunks(code.width())
+            .enumerate()
+            .flat_map(|(row, line)| {
+                line.iter()
+                    .enumerate()
+                    .filter(|(_, c)| **c == Color::Dark)
+                    .map(move |(col, _)| (col + quiet, side - quiet - row - 1))
+            })
+            .collect();
+        assert_eq!(drawn, expected);
+        text
+    }
+
+    #[test]
+    fn page_codes_carry_leaf_and_digest() {
+        let bytes = crate::utils::stamp::tests::pdf(2);
+        let opts = Options { mode: Mode::Content, profile: Profile::Raw, chunk_size: 0, metadata: false, extractor: None };
+        let extracted = extractor::extract(&bytes, "two.pdf", &opts).unwrap();
+        let leaves = proof_entries(&extracted.hashes);
+        let page_codes = (0..2)
+            .map(|i| page_code(&format!("https://example.org/documents/d/proof/{i}"), leaves.get(i)))
+            .collect();
+        let proofs = json!({ "leaves": leaves }).to_string().into_bytes();
+        let stamped = stamp_pdf(&bytes, &Stamp { root_hash: extracted.root.clone(), proofs, page_codes }).unwrap();
+
+        let pdf = lopdf::Document::load_mem(&stamped).unwrap();
+        for (i, page) in pdf.get_pages().into_values().enumerate() {
+            let code = read_code(&pdf, page);
+            let (url, query) = code.split_once('?').unwrap();
+            assert_eq!(url, format!("https://example.org/documents/d/proof/{i}"));
+            let leaf = query.split('&').find_map(|kv| kv.strip_prefix("leaf=")).unwrap();
+            let digest = query.split('&').find_map(|kv| kv.strip_prefix("digest=")).unwrap();
+            assert_eq!(leaf, extracted.hashes[i]);
+            let proof = proof_from_levels(&tree_levels(&extracted.hashes), i);
+            assert_eq!(digest, generate_hash(json!([leaf, proof]).to_string()));
+            assert_eq!(digest, leaves[i]["digest"]);
+        }
+    }
+
+    #[test]
+    fn only_loopback_hosts_stand_in_for_public_url() {
+        for host in ["localhost", "localhost:8080", "127.0.0.1:8080", "[::1]:8080", "[::1]"] {
+            assert!(is_loopback(host), "{host}");
+        }
+        for host in ["example.org", "evil.example:80", "10.0.0.1", "localhost.evil.example", "[2001:db8::1]:80", ""] {
+            assert!(!is_loopback(host), "{host}");
+        }
+    }
+}
@@ -0,0 +1,550 @@
+use actix_web::http::header::HttpDate;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, HttpResponseBuilder, Responder};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures_util::StreamExt;
+use mongodb::Database;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Write;
+use std::sync::Arc;
+use crate::db::blob::Blobs;
+use crate::db::uploads::{Info, Uploads};
+use crate::handlers::documents::{ingest, intake, max_upload_size, needs_password, Upload, UploadOptions};
+use crate::merkle::coreFunctions::generate_hash;
+use crate::utils::spool::Spooled;
+
+const TUS_VERSION: &str = "1.0.0";
+
+pub fn register(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/uploads")
+            .route(web::method(actix_web::http::Method::OPTIONS).to(options_route))
+            .route(web::post().to(create_route)),
+    );
+    cfg.service(
+        web::resource("/uploads/{id}")
+            .route(web::head().to(head_route))
+            .route(web::get().to(status_route))
+            .route(web::patch().to(patch_route))
+            .route(web::delete().to(delete_route)),
+    );
+}
+
+// every tus response but OPTIONS carries the protocol version
+fn tus(status: StatusCode) -> HttpResponseBuilder {
+    let mut resp = HttpResponse::build(status);
+    resp.insert_header(("Tus-Resumable", TUS_VERSION))
+        .insert_header(("Cache-Control", "no-store"));
+    resp
+}
+
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+fn wrong_version(req: &HttpRequest) -> Option<HttpResponse> {
+    match header(req, "Tus-Resumable") {
+        Some(TUS_VERSION) => None,
+        _ => Some(tus(StatusCode::PRECONDITION_FAILED)
+            .insert_header(("Tus-Version", TUS_VERSION))
+            .body(format!("Tus-Resumable: {TUS_VERSION} required"))),
+    }
+}
+
+async fn found(uploads: &Uploads, id: &str) -> Result<Info, HttpResponse> {
+    match uploads.info(id) {
+        Ok(Some(info)) => Ok(info),
+        Ok(None)       => Err(tus(StatusCode::NOT_FOUND).body("upload not found")),
+        Err(e)         => {
+            eprintln!("upload info error: {e:#}");
+            Err(tus(StatusCode::INTERNAL_SERVER_ERROR).body("upload store error"))
+        }
+    }
+}
+
+fn store_error(e: anyhow::Error) -> HttpResponse {
+    eprintln!("upload store error: {e:#}");
+    tus(StatusCode::INTERNAL_SERVER_ERROR).body("upload store error")
+}
+
+/// Server capabilities (tus core, creation, checksum, termination,
+/// expiration).
+async fn options_route() -> impl Responder {
+    HttpResponse::NoContent()
+        .insert_header(("Tus-Resumable", TUS_VERSION))
+        .insert_header(("Tus-Version", TUS_VERSION))
+        .insert_header(("Tus-Extension", "creation,checksum,termination,expiration"))
+        .insert_header(("Tus-Checksum-Algorithm", "sha1,sha256"))
+        .insert_header(("Tus-Max-Size", max_upload_size().to_string()))
+        .finish()
+}
+
+/// Start a resumable upload: `Upload-Length` plus `Upload-Metadata` with
+/// `filename`, optionally `filetype`, `password` (kept in memory only) and
+/// `sha256`, the hex digest the whole file must match. The query takes the
+/// `POST /documents` options, checked now and applied once the upload
+/// completes.
+async fn create_route(
+    req:     HttpRequest,
+    opts:    web::Query<UploadOptions>,
+    uploads: web::Data<Uploads>,
+) -> impl Responder {
+    if let Some(resp) = wrong_version(&req) {
+        return resp;
+    }
+    if let Err(e) = intake(&opts) {
+        return tus(StatusCode::BAD_REQUEST).body(e);
+    }
+    let length: u64 = match header(&req, "Upload-Length").map(str::parse) {
+        Some(Ok(n)) => n,
+        _ => return tus(StatusCode::BAD_REQUEST).body("Upload-Length required (deferred length is not supported)"),
+    };
+    if length > max_upload_size() as u64 {
+        return tus(StatusCode::PAYLOAD_TOO_LARGE)
+            .body(format!("file is larger than {} bytes (MAX_UPLOAD_SIZE)", max_upload_size()));
+    }
+
+    // `key base64value` pairs, comma separated
+    let mut filename = "upload.bin".to_owned();
+    let mut filetype = None;
+    let mut password = None;
+    let mut sha256 = None;
+    for pair in header(&req, "Upload-Metadata").unwrap_or_default().split(',') {
+        let mut kv = pair.trim().splitn(2, ' ');
+        let key = kv.next().unwrap_or_default();
+        let value = match STANDARD.decode(kv.next().unwrap_or_default()).map(String::from_utf8) {
+            Ok(Ok(v)) => v,
+            _ => return tus(StatusCode::BAD_REQUEST).body(format!("Upload-Metadata: bad value for `{key}`")),
+        };
+        match key {
+            "filename" | "name" => filename = value,
+            "filetype" | "type" => filetype = Some(value),
+            "password"          => password = Some(value),
+            "sha256"            => {
+                if value.len() != 64 || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return tus(StatusCode::BAD_REQUEST).body("Upload-Metadata: sha256 must be 64 hex digits");
+                }
+                sha256 = Some(value.to_ascii_lowercase());
+            }
+            _ => {}
+        }
+    }
+
+    let info = Info {
+        length,
+        filename,
+        filetype,
+        query:  req.query_string().to_owned(),
+        sha256,
+        result: None,
+    };
+    let id = match uploads.create(&info, password) {
+        Ok(id) => id,
+        Err(e) => return store_error(e),
+    };
+    tus(StatusCode::CREATED)
+        .insert_header(("Location", format!("/uploads/{id}")))
+        .insert_header(("Upload-Expires", HttpDate::from(uploads.expires(&id)).to_string()))
+        .finish()
+}
+
+/// Where to resume: `Upload-Offset` of `Upload-Length`.
+async fn head_route(
+    req:     HttpRequest,
+    path:    web::Path<String>,
+    uploads: web::Data<Uploads>,
+) -> impl Responder {
+    if let Some(resp) = wrong_version(&req) {
+        return resp;
+    }
+    let id = path.into_inner();
+    let info = match found(&uploads, &id).await {
+        Ok(i)     => i,
+        Err(resp) => return resp,
+    };
+    let offset = match uploads.offset(&id, &info) {
+        Ok(o)  => o,
+        Err(e) => return store_error(e),
+    };
+    tus(StatusCode::OK)
+        .insert_header(("Upload-Offset", offset.to_string()))
+        .insert_header(("Upload-Length", info.length.to_string()))
+        .finish()
+}
+
+/// Progress as JSON, and once ingested the `POST /documents` response, so
+/// a client that lost the final reply can still learn its document.
+async fn status_route(path: web::Path<String>, uploads: web::Data<Uploads>) -> impl Responder {
+    let id = path.into_inner();
+    let info = match found(&uploads, &id).await {
+        Ok(i)     => i,
+        Err(resp) => return resp,
+    };
+    let offset = match uploads.offset(&id, &info) {
+        Ok(o)  => o,
+        Err(e) => return store_error(e),
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "complete": info.result.is_some(),
+        "length":   info.length,
+        "offset":   offset,
+        "result":   info.result,
+    }))
+}
+
+/// Append a chunk at `Upload-Offset`, verified against `Upload-Checksum`
+/// (`sha1` or `sha256`, base64) when given; a mismatched chunk is dropped
+/// with 460. The chunk that completes the upload runs the
+/// `POST /documents` pipeline and returns its response. `Upload-Password`
+/// (base64) sets the password of an encrypted PDF, e.g. on an empty PATCH
+/// at the final offset once the upload asked for it with 403.
+async fn patch_route(
+    req:     HttpRequest,
+    path:    web::Path<String>,
+    mut body: web::Payload,
+    uploads: web::Data<Uploads>,
+    db:      web::Data<Database>,
+    blobs:   web::Data<Blobs>,
+) -> impl Responder {
+    if let Some(resp) = wrong_version(&req) {
+        return resp;
+    }
+    if header(&req, "Content-Type") != Some("application/offset+octet-stream") {
+        return tus(StatusCode::UNSUPPORTED_MEDIA_TYPE).body("Content-Type must be application/offset+octet-stream");
+    }
+    let id = path.into_inner();
+    let Some(lock) = uploads.lock(&id) else {
+        return tus(StatusCode::LOCKED).body("upload is busy with another request");
+    };
+    // read under the lock: a PATCH that just completed the upload has
+    // recorded its result and removed the part
+    let info = match found(&uploads, &id).await {
+        Ok(i)     => i,
+        Err(resp) => return resp,
+    };
+    if let Some(value) = header(&req, "Upload-Password") {
+        match STANDARD.decode(value).map(String::from_utf8) {
+            Ok(Ok(pw)) => uploads.set_password(&id, pw),
+            _          => return tus(StatusCode::BAD_REQUEST).body("Upload-Password must be base64 UTF-8"),
+        }
+    }
+
+    // ── 1. the chunk must start where the upload stands ──────────────────
+    let offset = match uploads.offset(&id, &info) {
+        Ok(o)  => o,
+        Err(e) => return store_error(e),
+    };
+    if header(&req, "Upload-Offset").and_then(|v| v.parse().ok()) != Some(offset) {
+        return tus(StatusCode::CONFLICT)
+            .insert_header(("Upload-Offset", offset.to_string()))
+            .body(format!("Upload-Offset must be {offset}"));
+    }
+    if let Some(result) = info.result {
+        return tus(StatusCode::OK)
+            .insert_header(("Upload-Offset", offset.to_string()))
+            .json(result);
+    }
+    let mut checksum = match header(&req, "Upload-Checksum").map(Checksum::parse) {
+        None         => None,
+        Some(Ok(c))  => Some(c),
+        Some(Err(e)) => return tus(StatusCode::BAD_REQUEST).body(e),
+    };
+
+    // ── 2. append, hashing as it goes ────────────────────────────────────
+    let file = match uploads.append(&id, &lock) {
+        Ok(f)  => Arc::new(f),
+        Err(e) => return store_error(e),
+    };
+    let mut received = offset;
+    let mut failure = None;
+    while let Some(chunk) = body.next().await {
+        let data = match chunk {
+            Ok(data) => data,
+            Err(e)   => {
+                failure = Some(tus(StatusCode::BAD_REQUEST).body(format!("stream error: {e}")));
+                break;
+            }
+        };
+        if received + data.len() as u64 > info.length {
+            failure = Some(tus(StatusCode::BAD_REQUEST).body("chunk runs past Upload-Length"));
+            break;
+        }
+        let chunk = data.clone();
+        if let Err(e) = on_file(&file, move |mut f| f.write_all(&chunk)).await {
+            failure = Some(store_error(e));
+            break;
+        }
+        if let Some(c) = &mut checksum {
+            c.update(&data);
+        }
+        received += data.len() as u64;
+    }
+    // a broken-off chunk keeps what arrived, unless it was to be verified
+    let failure = match (failure, checksum.map(Checksum::matches)) {
+        (None, None | Some(true)) => None,
+        (None, Some(false))       => {
+            Some(tus(StatusCode::from_u16(460).unwrap()).body("chunk does not match Upload-Checksum"))
+        }
+        (Some(resp), None)        => {
+            if let Err(e) = on_file(&file, |f| f.sync_data()).await {
+                return store_error(e);
+            }
+            return resp;
+        }
+        (Some(resp), Some(_))     => Some(resp),
+    };
+    if let Some(resp) = failure {
+        if let Err(e) = on_file(&file, move |f| f.set_len(offset)).await {
+            return store_error(e);
+        }
+        return resp;
+    }
+    drop(file);
+
+    if received < info.length {
+        return tus(StatusCode::NO_CONTENT)
+            .insert_header(("Upload-Offset", received.to_string()))
+            .insert_header(("Upload-Expires", HttpDate::from(uploads.expires(&id)).to_string()))
+            .finish();
+    }
+
+    // ── 3. complete: check the whole file, then ingest it ────────────────
+    complete(&uploads, &id, info, &db, &blobs).await
+}
+
+// File I/O of a PATCH runs on the blocking pool, one call at a time.
+async fn on_file<F>(file: &Arc<File>, op: F) -> anyhow::Result<()>
+where
+    F: FnOnce(&File) -> std::io::Result<()> + Send + 'static,
+{
+    let file = Arc::clone(file);
+    actix_web::rt::task::spawn_blocking(move || op(&file)).await??;
+    Ok(())
+}
+
+async fn complete(uploads: &Uploads, id: &str, mut info: Info, db: &Database, blobs: &Blobs) -> HttpResponse {
+    // SAFETY: the upload is locked and complete; nothing writes to the
+    // part again before it is removed
+    let bytes = match unsafe { Spooled::open(&uploads.part(id)) } {
+        Ok(b)  => Arc::new(b),
+        Err(e) => return store_error(e.into()),
+    };
+    if let Some(expected) = &info.sha256 {
+        let file = Arc::clone(&bytes);
+        let hash = match actix_web::rt::task::spawn_blocking(move || generate_hash(&file[..])).await {
+            Ok(h)  => h,
+            Err(e) => return store_error(e.into()),
+        };
+        if hash != *expected {
+            drop(bytes);
+            if let Err(e) = uploads.remove(id) {
+                return store_error(e);
+            }
+            return tus(StatusCode::from_u16(460).unwrap()).body("upload does not match its sha256; start over");
+        }
+    }
+
+    let opts = match web::Query::<UploadOptions>::from_query(&info.query) {
+        Ok(q)  => q.into_inner(),
+        Err(e) => return tus(StatusCode::BAD_REQUEST).body(format!("bad options: {e}")),
+    };
+    let intake = match intake(&opts) {
+        Ok(i)  => i,
+        Err(e) => return tus(StatusCode::BAD_REQUEST).body(e),
+    };
+    let upload = Upload {
+        bytes,
+        filename:     info.filename.clone(),
+        content_type: info.filetype.clone(),
+        password:     uploads.password(id),
+    };
+    match ingest(db, blobs, &upload, &intake).await {
+        Ok(result) => {
+            info.result = Some(result.clone());
+            if let Err(e) = uploads.save(id, &info).and_then(|_| uploads.finish(id)) {
+                return store_error(e);
+            }
+            tus(StatusCode::OK)
+                .insert_header(("Upload-Offset", info.length.to_string()))
+                .json(result)
+        }
+        // kept for a retry (an empty PATCH at the final offset) when the
+        // failure was ours or the password is missing, e.g. lost in a
+        // restart; dropped when the file itself was rejected
+        Err(_) if needs_password(&upload, intake.opts.mode) => tus(StatusCode::FORBIDDEN)
+            .insert_header(("Upload-Offset", info.length.to_string()))
+            .body(format!(
+                "PDF is encrypted and the password is {}; send it base64 in Upload-Password \
+                 with an empty PATCH at offset {}",
+                if upload.password.is_some() { "wrong" } else { "missing" },
+                info.length,
+            )),
+        Err(resp) => {
+            if !resp.status().is_server_error() {
+                if let Err(e) = uploads.remove(id) {
+                    return store_error(e);
+                }
+            }
+            resp
+        }
+    }
+}
+
+/// Abandon an upload.
+async fn delete_route(
+    req:     HttpRequest,
+    path:    web::Path<String>,
+    uploads: web::Data<Uploads>,
+) -> impl Responder {
+    if let Some(resp) = wrong_version(&req) {
+        return resp;
+    }
+    let id = path.into_inner();
+    if let Err(resp) = found(&uploads, &id).await {
+        return resp;
+    }
+    let Some(_lock) = uploads.lock(&id) else {
+        return tus(StatusCode::LOCKED).body("upload is busy with another request");
+    };
+    match uploads.remove(&id) {
+        Ok(()) => tus(StatusCode::NO_CONTENT).finish(),
+        Err(e) => store_error(e),
+    }
+}
+
+enum Checksum {
+    Sha1(Sha1, Vec<u8>),
+    Sha256(Sha256, Vec<u8>),
+}
+
+impl Checksum {
+    // `Upload-Checksum: <algorithm> <base64 digest>`
+    fn parse(value: &str) -> Result<Checksum, String> {
+        let (algorithm, digest) = value.split_once(' ').unwrap_or((value, ""));
+        let Ok(digest) = STANDARD.decode(digest) else {
+            return Err("Upload-Checksum digest must be base64".into());
+        };
+        match algorithm {
+            "sha1"   => Ok(Checksum::Sha1(Sha1::new(), digest)),
+            "sha256" => Ok(Checksum::Sha256(Sha256::new(), digest)),
+            other    => Err(format!("unsupported checksum algorithm: {other} (sha1 or sha256)")),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Checksum::Sha1(h, _)   => h.update(data),
+            Checksum::Sha256(h, _) => h.update(data),
+        }
+    }
+
+    fn matches(self) -> bool {
+        match self {
+            Checksum::Sha1(h, expected)   => h.finalize().as_slice() == expected,
+            Checksum::Sha256(h, expected) => h.finalize().as_slice() == expected,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+    use std::time::Duration;
+
+    async fn state(dir: &std::path::Path) -> (web::Data<Uploads>, web::Data<Database>, web::Data<Blobs>) {
+        // never connects: these requests don't get as far as ingesting
+        let db = mongodb::Client::with_uri_str("mongodb://127.0.0.1:9").await.unwrap().database("test");
+        let uploads = Uploads::new(dir.to_owned(), Duration::from_secs(3600)).unwrap();
+        (web::Data::new(uploads), web::Data::new(db), web::Data::new(None))
+    }
+
+    fn create(length: u64) -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/uploads")
+            .insert_header(("Tus-Resumable", TUS_VERSION))
+            .insert_header(("Upload-Length", length.to_string()))
+    }
+
+    fn patch(location: &str, offset: u64, body: &'static [u8]) -> test::TestRequest {
+        test::TestRequest::patch()
+            .uri(location)
+            .insert_header(("Tus-Resumable", TUS_VERSION))
+            .insert_header(("Content-Type", "application/offset+octet-stream"))
+            .insert_header(("Upload-Offset", offset.to_string()))
+            .set_payload(body)
+    }
+
+    fn head(resp: &actix_web::dev::ServiceResponse, name: &str) -> String {
+        resp.headers().get(name).unwrap().to_str().unwrap().to_owned()
+    }
+
+    #[actix_web::test]
+    async fn chunks_start_at_the_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let (uploads, db, blobs) = state(dir.path()).await;
+        let app = test::init_service(App::new().app_data(uploads).app_data(db).app_data(blobs).configure(register)).await;
+
+        let resp = test::call_service(&app, create(4).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let location = head(&resp, "Location");
+
+        let resp = test::call_service(&app, patch(&location, 2, b"ab").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(head(&resp, "Upload-Offset"), "0");
+
+        let resp = test::call_service(&app, patch(&location, 0, b"ab").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_eq!(head(&resp, "Upload-Offset"), "2");
+    }
+
+    #[actix_web::test]
+    async fn chunks_past_the_length_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let (uploads, db, blobs) = state(dir.path()).await;
+        let app = test::init_service(App::new().app_data(uploads).app_data(db).app_data(blobs).configure(register)).await;
+
+        let location = head(&test::call_service(&app, create(4).to_request()).await, "Location");
+        let resp = test::call_service(&app, patch(&location, 0, b"abcdef").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::default()
+            .method(actix_web::http::Method::HEAD)
+            .uri(&location)
+            .insert_header(("Tus-Resumable", TUS_VERSION))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(head(&resp, "Upload-Offset"), "0");
+    }
+
+    #[actix_web::test]
+    async fn completed_uploads_answer_with_their_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let (uploads, db, blobs) = state(dir.path()).await;
+        let app = test::init_service(
+            App::new().app_data(uploads.clone()).app_data(db).app_data(blobs).configure(register),
+        )
+        .await;
+
+        let location = head(&test::call_service(&app, create(4).to_request()).await, "Location");
+        // what the completing PATCH leaves behind: the result, no part
+        let id = location.trim_start_matches("/uploads/");
+        let mut info = uploads.info(id).unwrap().unwrap();
+        info.result = Some(serde_json::json!({ "document_id": "d" }));
+        uploads.save(id, &info).unwrap();
+        uploads.finish(id).unwrap();
+
+        // a PATCH that raced the completing one
+        let resp = test::call_service(&app, patch(&location, 0, b"ab").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        assert_eq!(head(&resp, "Upload-Offset"), "4");
+
+        // a retried final PATCH
+        let resp = test::call_service(&app, patch(&location, 4, b"").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["document_id"], "d");
+    }
+}
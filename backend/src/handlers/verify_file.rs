@@ -0,0 +1,139 @@
+use actix_multipart::Multipart;
+use actix_web::{web, HttpResponse, Responder};
+use futures_util::StreamExt;
+use mongodb::{bson::{doc, Document}, Database};
+use serde_json::json;
+use std::collections::HashMap;
+use crate::handlers::documents::{canonical_id, decrypt_upload, read_upload, recorded_options};
+use crate::handlers::proof::n_leaves;
+use crate::merkle::coreFunctions::generate_hash;
+use crate::utils::extractor;
+
+pub fn register(cfg: &mut web::ServiceConfig) {
+    cfg.route("/documents/{id}/verify-file", web::post().to(verify_file_route));
+}
+
+/// Upload a file (multipart, plus `password` if it is an encrypted PDF) →
+/// is it the document that was anchored? Re-extracted with the document's
+/// recorded settings and compared leaf by leaf: `match`, `altered`,
+/// `missing` (stored, not in the file) or `extra` (in the file only).
+/// Altered leaves found at another index carry `found_at`.
+async fn verify_file_route(
+    path:        web::Path<String>,
+    mut payload: Multipart,
+    db:          web::Data<Database>,
+) -> impl Responder {
+    let doc_id = path.into_inner();
+    let doc_id = match canonical_id(&db, doc_id).await {
+        Ok(id)    => id,
+        Err(resp) => return resp,
+    };
+
+    // ── 1. document metadata ─────────────────────────────────────────────
+    let doc_meta = match db
+        .collection::<Document>("documents")
+        .find_one(doc! { "_id": &doc_id }, None)
+        .await
+    {
+        Ok(Some(d)) => d,
+        Ok(None)    => return HttpResponse::NotFound().body("document not found"),
+        Err(e)      => {
+            eprintln!("find document error: {e}");
+            return HttpResponse::InternalServerError().body("db error");
+        }
+    };
+    let root_hash = doc_meta.get_str("root_hash").unwrap_or_default().to_owned();
+    let opts = recorded_options(&doc_meta);
+
+    // ── 2. re-extract the upload as the document was ─────────────────────
+    let upload = match read_upload(&mut payload).await {
+        Ok(u)     => u,
+        Err(resp) => return resp,
+    };
+    let file_hash = generate_hash(&upload.bytes[..]);
+    let decrypted = match decrypt_upload(&upload, opts.mode) {
+        Ok(plain) => plain,
+        Err(e)    => return HttpResponse::BadRequest().body(format!("extract error: {e:#}")),
+    };
+    let bytes: &[u8] = decrypted.as_deref().unwrap_or(&upload.bytes);
+    let extracted = match extractor::extract(bytes, &upload.detect_name(), &opts) {
+        Ok(x)  => x,
+        Err(e) => return HttpResponse::BadRequest().body(format!("extract error: {e:#}")),
+    };
+
+    // ── 3. the stored leaves ─────────────────────────────────────────────
+    let n_leaves = n_leaves(&doc_meta);
+    let mut stored = vec![(String::new(), None); n_leaves];
+    let mut cursor = match db
+        .collection::<Document>("pages")
+        .find(doc! { "document_id": &doc_id }, None)
+        .await
+    {
+        Ok(c)  => c,
+        Err(e) => {
+            eprintln!("find pages error: {e}");
+            return HttpResponse::InternalServerError().body("db error");
+        }
+    };
+    while let Some(Ok(p)) = cursor.next().await {
+        let idx = p.get_i32("page_index").unwrap_or(-1);
+        if let Some(slot) = usize::try_from(idx).ok().and_then(|i| stored.get_mut(i)) {
+            *slot = (
+                p.get_str("page_hash").unwrap_or_default().to_owned(),
+                p.get_str("label").ok().map(str::to_owned),
+            );
+        }
+    }
+
+    // ── 4. compare leaf by leaf ──────────────────────────────────────────
+    let mut stored_at: HashMap<&str, usize> = HashMap::new();
+    for (i, (hash, _)) in stored.iter().enumerate().rev() {
+        stored_at.insert(hash, i);
+    }
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut pages = Vec::with_capacity(n_leaves.max(extracted.hashes.len()));
+    for index in 0..n_leaves.max(extracted.hashes.len()) {
+        let (stored_hash, label) = match stored.get(index) {
+            Some((hash, label)) => (Some(hash.as_str()), label.as_deref()),
+            None                => (None, None),
+        };
+        let file_hash = extracted.hashes.get(index).map(String::as_str);
+        let status = match (stored_hash, file_hash) {
+            (Some(s), Some(f)) if s == f => "match",
+            (Some(_), Some(_))           => "altered",
+            (Some(_), None)              => "missing",
+            (None, _)                    => "extra",
+        };
+        *counts.entry(status).or_default() += 1;
+
+        let mut entry = json!({
+            "index":       index,
+            "status":      status,
+            "stored_hash": stored_hash,
+            "file_hash":   file_hash,
+        });
+        if let Some(label) = label {
+            entry["label"] = label.into();
+        }
+        if let Some(&at) = file_hash.filter(|_| status != "match").and_then(|h| stored_at.get(h)) {
+            entry["found_at"] = at.into();
+        }
+        pages.push(entry);
+    }
+
+    HttpResponse::Ok().json(json!({
+        "document_id":    doc_id,
+        "extract_mode":   opts.mode.id(),
+        "root_hash":      root_hash,
+        "computed_root":  extracted.root,
+        "root_match":     extracted.root == root_hash,
+        "identical_file": doc_meta.get_str("file_hash").ok().map(|h| h == file_hash),
+        "summary": {
+            "altered":  counts.get("altered").copied().unwrap_or(0),
+            "extra":    counts.get("extra").copied().unwrap_or(0),
+            "match":    counts.get("match").copied().unwrap_or(0),
+            "missing":  counts.get("missing").copied().unwrap_or(0),
+        },
+        "pages": pages,
+    }))
+}
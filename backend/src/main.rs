@@ -1,5 +1,7 @@
 mod db {
+    pub mod blob;
     pub mod mongo;
+    pub mod uploads;
 }
 mod handlers;
 mod merkle;
@@ -10,6 +12,7 @@ use actix_web::{web, App, HttpResponse, HttpServer, Responder};
 use dotenvy::dotenv;
 use mongodb::bson::doc;
 use std::env;
+use std::time::Duration;
 
 async fn index() -> impl Responder {
     "OK"
@@ -43,7 +46,44 @@ async fn main() -> std::io::Result<()> {
         .await
         .expect("MongoDB ping failed");
 
+    db::mongo::ensure_indexes(&db)
+        .await
+        .expect("Could not create MongoDB indexes");
+
     println!("Connected to MongoDB");
+
+    let blobs = db::blob::from_env(&db).expect("Could not open blob store");
+    if let Some(store) = blobs.clone() {
+        println!("Keeping originals in {}", store.name());
+        let db = db.clone();
+        actix_web::rt::spawn(async move {
+            let mut tick = actix_web::rt::time::interval(Duration::from_secs(3600));
+            loop {
+                tick.tick().await;
+                match db::blob::sweep(&db, store.as_ref()).await {
+                    Ok(0)  => {}
+                    Ok(n)  => println!("Retention sweep: {n} originals deleted"),
+                    Err(e) => eprintln!("Retention sweep failed: {e:#}"),
+                }
+            }
+        });
+    }
+    // shared across workers: uploads in progress are locked here
+    let uploads = web::Data::new(db::uploads::Uploads::from_env().expect("Could not open upload dir"));
+    {
+        let uploads = uploads.clone();
+        actix_web::rt::spawn(async move {
+            let mut tick = actix_web::rt::time::interval(Duration::from_secs(3600));
+            loop {
+                tick.tick().await;
+                match uploads.sweep() {
+                    Ok(0)  => {}
+                    Ok(n)  => println!("Upload sweep: {n} expired uploads removed"),
+                    Err(e) => eprintln!("Upload sweep failed: {e:#}"),
+                }
+            }
+        });
+    }
     println!("Server running on http://{host}:{port}");
 
     HttpServer::new(move || {
@@ -51,14 +91,25 @@ async fn main() -> std::io::Result<()> {
             .allow_any_origin()
             .allow_any_method()
             .allow_any_header()
+            .expose_any_header()
             .max_age(3600);
 
         App::new()
             .wrap(cors)
             .app_data(web::Data::new(db.clone()))
+            .app_data(web::Data::new(blobs.clone()))
+            .app_data(uploads.clone())
             .configure(handlers::documents::register)
             .configure(handlers::proof::register)
+            .configure(handlers::pages::register)
+            .configure(handlers::stamp::register)
+            .configure(handlers::uploads::register)
+            .configure(handlers::roots::register)
+            .configure(handlers::lookup::register)
+            .configure(handlers::diff::register)
+            .configure(handlers::disclose::register)
             .configure(handlers::verify::register)
+            .configure(handlers::verify_file::register)
             .route("/", web::get().to(index))
             .route("/db-check", web::get().to(db_check))
     })
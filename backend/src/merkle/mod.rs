@@ -1,6 +1,6 @@
 use sha2::{Digest, Sha256};
-use std::collections::VecDeque;
 
+#[allow(non_snake_case)]
 pub mod coreFunctions {
     use super::*;
 
@@ -19,20 +19,6 @@ pub mod coreFunctions {
                 right: None,
             }
         }
-
-        pub fn add_left_node(&mut self, node: Node) {
-            if self.left.is_some() {
-                panic!("Left node already exists");
-            }
-            self.left = Some(Box::new(node));
-        }
-
-        pub fn add_right_node(&mut self, node: Node) {
-            if self.right.is_some() {
-                panic!("Right node already exists");
-            }
-            self.right = Some(Box::new(node));
-        }
     }
 
     pub fn generate_hash<T: AsRef<[u8]>>(input: T) -> String {
@@ -50,40 +36,6 @@ pub mod coreFunctions {
         format!("{:x}", result)
     }
 
-    pub fn build_merkle_tree(data: Vec<String>) -> Node {
-        if data.is_empty() {
-            panic!("No data given to build the tree");
-        }
-
-        let mut leaves: Vec<Node> = data
-            .iter()
-            .map(|d| Node::new(generate_hash(d)))
-            .collect();
-
-        while leaves.len() > 1 {
-            let mut next_level = Vec::new();
-
-            for i in (0..leaves.len()).step_by(2) {
-                let left = leaves[i].clone();
-                let right = if i + 1 < leaves.len() {
-                    leaves[i + 1].clone()
-                } else {
-                    left.clone() // duplicate if odd number
-                };
-
-                let parent_hash = concat_hash(&left.hash, &right.hash);
-                let mut parent_node = Node::new(parent_hash);
-                parent_node.left = Some(Box::new(left));
-                parent_node.right = Some(Box::new(right));
-                next_level.push(parent_node);
-            }
-
-            leaves = next_level;
-        }
-
-        leaves[0].clone()
-    }
-
     // this is used to build the tree if we already have leaf hashes
     pub fn build_tree_from_hashes(hashes: Vec<String>) -> Node {
         if hashes.is_empty() {
@@ -108,49 +60,45 @@ pub mod coreFunctions {
     }
     
 
-    pub fn generate_proof(root: &Node, target_hash: &str) -> Vec<(String, String)> {
-        let mut proof = Vec::new();
-        let mut queue = VecDeque::new();
-        queue.push_back((root.clone(), Vec::new()));
     
-        while let Some((node, path)) = queue.pop_front() {
-            // if target is left
-            if let (Some(ref left), Some(ref right)) = (&node.left, &node.right) {
-                if left.hash == target_hash {
-                    let mut new_path = path.clone();
-                    new_path.push((right.hash.clone(), "R".to_string()));
-                    proof = new_path;
-                    break;
-                } else if right.hash == target_hash {
-                    let mut new_path = path.clone();
-                    new_path.push((left.hash.clone(), "L".to_string()));
-                    proof = new_path;
-                    break;
-                }
-            }
-    
-            if let Some(ref left) = node.left {
-                let mut left_path = path.clone();
-                if let Some(ref right) = node.right {
-                    left_path.push((right.hash.clone(), "R".to_string()));
-                }
-                queue.push_back(((*left).as_ref().clone(), left_path));
-            }
-    
-            if let Some(ref right) = node.right {
-                let mut right_path = path.clone();
-                if let Some(ref left) = node.left {
-                    right_path.push((left.hash.clone(), "L".to_string()));
-                }
-                queue.push_back(((*right).as_ref().clone(), right_path));
+
+    // Every level of the tree over `hashes`, leaves first and root last,
+    // pairing an odd node with itself as `build_tree_from_hashes` does.
+    pub fn tree_levels(hashes: &[String]) -> Vec<Vec<String>> {
+        let mut levels = vec![hashes.to_vec()];
+        while levels[levels.len() - 1].len() > 1 {
+            let next = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| concat_hash(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+                .collect();
+            levels.push(next);
+        }
+        levels
+    }
+
+    // Proof for the leaf at `index` read off `tree_levels`, so proving many
+    // leaves of one tree hashes it once.
+    pub fn proof_from_levels(levels: &[Vec<String>], index: usize) -> Vec<(String, String)> {
+        let mut proof = Vec::with_capacity(levels.len().saturating_sub(1));
+        let mut idx = index;
+        for level in levels.iter().take_while(|level| level.len() > 1) {
+            if idx.is_multiple_of(2) {
+                let right = level.get(idx + 1).unwrap_or(&level[idx]);
+                proof.push((right.clone(), "R".to_string()));
+            } else {
+                proof.push((level[idx - 1].clone(), "L".to_string()));
             }
+            idx /= 2;
         }
-    
-        proof.reverse();
         proof
     }
-    
-    
+
+    // Proof for the leaf at `index`, walking the same levels as
+    // `build_tree_from_hashes` (odd node paired with itself). Unlike
+    // `generate_proof` this stays correct when several leaves share a hash.
+    pub fn generate_proof_for_index(hashes: &[String], index: usize) -> Vec<(String, String)> {
+        proof_from_levels(&tree_levels(hashes), index)
+    }
 
     pub fn verify_proof(root_hash: &str, target_hash: &str, proof: Vec<(String, String)>) -> bool {
         let mut current_hash = target_hash.to_string();
@@ -166,27 +114,37 @@ pub mod coreFunctions {
         current_hash == root_hash
     }
 
-    pub fn test() {
-        let data = vec![
-            "data1".to_string(),
-            "data2".to_string(),
-            "data3".to_string(),
-            "data4".to_string(),
-        ];
+}
 
-        let root = build_merkle_tree(data.clone());
-        println!("Root hash: {}", root.hash);
+#[cfg(test)]
+mod tests {
+    use super::coreFunctions::*;
 
-        let target_data = "data3";
-        let target_hash = generate_hash(target_data);
-        let proof = generate_proof(&root, &target_hash);
+    fn leaves(n: usize) -> Vec<String> {
+        (0..n).map(|i| generate_hash(i.to_string())).collect()
+    }
 
-        println!("Proof for '{}': {:?}", target_data, proof);
+    #[test]
+    fn levels_end_at_the_root() {
+        for n in 1..=9 {
+            let hashes = leaves(n);
+            let levels = tree_levels(&hashes);
+            assert_eq!(levels[0], hashes);
+            assert_eq!(levels.last().unwrap(), &vec![build_tree_from_hashes(hashes.clone()).hash]);
+        }
+    }
 
-        let is_valid = verify_proof(&root.hash, &target_hash, proof);
-        println!(
-            "Is '{}' part of the Merkle tree? {}",
-            target_data, is_valid
-        );
+    #[test]
+    fn proofs_from_levels_verify() {
+        for n in 1..=9 {
+            let hashes = leaves(n);
+            let levels = tree_levels(&hashes);
+            let root = &levels[levels.len() - 1][0];
+            for (i, hash) in hashes.iter().enumerate() {
+                let proof = proof_from_levels(&levels, i);
+                assert_eq!(proof, generate_proof_for_index(&hashes, i));
+                assert!(verify_proof(root, hash, proof));
+            }
+        }
     }
 }
@@ -0,0 +1,151 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// Versioned text canonicalization applied to every extracted page before
+/// hashing. A profile id is stored with each document, so the rules of an
+/// existing id must never change — add a new variant instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Extracted text is hashed exactly as returned by the extractor.
+    Raw,
+    /// Line endings, NFC, de-hyphenation, whitespace collapsing.
+    NfcV1,
+    /// Same as `NfcV1` but with compatibility decomposition (ligatures,
+    /// full-width forms, … fold to their plain equivalents).
+    NfkcV1,
+}
+
+impl Profile {
+    /// Hashes text as extracted, so roots match those from before
+    /// profiles existed; normalization is opt-in.
+    pub const DEFAULT: Profile = Profile::Raw;
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Profile::Raw    => "raw",
+            Profile::NfcV1  => "nfc-v1",
+            Profile::NfkcV1 => "nfkc-v1",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Profile> {
+        match id {
+            "raw"     => Some(Profile::Raw),
+            "nfc-v1"  => Some(Profile::NfcV1),
+            "nfkc-v1" => Some(Profile::NfkcV1),
+            _         => None,
+        }
+    }
+
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            Profile::Raw => text.to_owned(),
+            Profile::NfcV1 => {
+                let text: String = normalize_line_endings(text).nfc().collect();
+                collapse_whitespace(&dehyphenate(&text))
+            }
+            Profile::NfkcV1 => {
+                let text: String = normalize_line_endings(text).nfkc().collect();
+                collapse_whitespace(&dehyphenate(&text))
+            }
+        }
+    }
+}
+
+// CRLF, CR and the Unicode line/paragraph separators all become '\n'
+fn normalize_line_endings(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                out.push('\n');
+            }
+            '\u{0085}' | '\u{2028}' | '\u{2029}' => out.push('\n'),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// drops soft hyphens and joins "exam-\nple" → "example" when the word
+// continues in lower case on the next line
+fn dehyphenate(text: &str) -> String {
+    let chars: Vec<char> = text.chars().filter(|&c| c != '\u{00AD}').collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if (c == '-' || c == '\u{2010}') && i > 0 && chars[i - 1].is_alphabetic() {
+            // skip trailing blanks, one newline, then leading blanks
+            let mut j = i + 1;
+            while j < chars.len() && chars[j] != '\n' && chars[j].is_whitespace() {
+                j += 1;
+            }
+            if j < chars.len() && chars[j] == '\n' {
+                j += 1;
+                while j < chars.len() && chars[j] != '\n' && chars[j].is_whitespace() {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_lowercase() {
+                    i = j;
+                    continue;
+                }
+            }
+        }
+        out.push(c);
+        i += 1;
+    }
+    out
+}
+
+// every run of Unicode whitespace (incl. newlines and NBSP) → one space
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_round_trip() {
+        for p in [Profile::Raw, Profile::NfcV1, Profile::NfkcV1] {
+            assert_eq!(Profile::from_id(p.id()), Some(p));
+        }
+        assert_eq!(Profile::from_id("nfkc"), None);
+    }
+
+    #[test]
+    fn raw_is_untouched() {
+        let text = "a\r\n  ﬁ-\nne\u{00AD}";
+        assert_eq!(Profile::Raw.apply(text), text);
+    }
+
+    #[test]
+    fn line_endings_and_whitespace() {
+        let text = " one\r\ntwo\rthree\u{2028}four\u{00A0}\u{00A0}five\t\n";
+        assert_eq!(Profile::NfcV1.apply(text), "one two three four five");
+    }
+
+    #[test]
+    fn hyphenated_words_are_joined_in_lower_case_only() {
+        assert_eq!(Profile::NfcV1.apply("exam-\r\n  ple"), "example");
+        assert_eq!(Profile::NfcV1.apply("exam- \n ple"), "example");
+        assert_eq!(Profile::NfcV1.apply("exam\u{2010}\nple"), "example");
+        assert_eq!(Profile::NfcV1.apply("Jean-\nPaul"), "Jean- Paul");
+        assert_eq!(Profile::NfcV1.apply("page 2-\nthree"), "page 2- three");
+        assert_eq!(Profile::NfcV1.apply("exam-\n\nple"), "exam- ple");
+        assert_eq!(Profile::NfcV1.apply("end-"), "end-");
+        assert_eq!(Profile::NfcV1.apply("soft\u{00AD}ly"), "softly");
+    }
+
+    #[test]
+    fn compatibility_forms_fold_in_nfkc_only() {
+        let text = "e\u{0301} ﬁle ＡＢ";
+        assert_eq!(Profile::NfcV1.apply(text), "\u{00E9} ﬁle ＡＢ");
+        assert_eq!(Profile::NfkcV1.apply(text), "\u{00E9} file AB");
+    }
+}
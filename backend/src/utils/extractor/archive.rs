@@ -0,0 +1,163 @@
+use anyhow::{bail, Context, Result};
+use std::io::{Cursor, Read};
+use super::{registry, Extracted, Format, Member, Mode, Options, PageLeaf, Source, Status, Unit};
+
+const MAX_MEMBERS:  usize = 10_000;
+const MAX_UNPACKED: u64   = 512 * 1024 * 1024;
+
+/// Preimage of an archive member's leaf: its path bound to the root of its
+/// own page tree, so a page proof chains page → file → archive root.
+pub fn member_leaf_payload(path: &str, root: &str) -> Vec<u8> {
+    let mut out = b"member-v1\n".to_vec();
+    out.extend_from_slice(format!("P{}:", path.len()).as_bytes());
+    out.extend_from_slice(path.as_bytes());
+    out.extend_from_slice(format!("R{}:", root.len()).as_bytes());
+    out.extend_from_slice(root.as_bytes());
+    out
+}
+
+/// Regular files of a ZIP/TAR archive sorted by path — re-packing the same
+/// files in another order keeps the root — each extracted on its own.
+pub(super) fn extract(bytes: &[u8], format: Format, opts: &Options) -> Result<Extracted> {
+    let mut files = match format {
+        Format::Zip => zip_files(bytes)?,
+        Format::Tar => tar_files(bytes)?,
+        _           => bail!("{} is not an archive", format.id()),
+    };
+    if files.is_empty() {
+        bail!("archive contains no files");
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut leaves  = Vec::with_capacity(files.len());
+    let mut members = Vec::with_capacity(files.len());
+    for (index, (path, data)) in files.into_iter().enumerate() {
+        // one unreadable file doesn't sink the bundle: its bytes are chunked
+        let (member, error) = match extract_member(&data, &path, opts) {
+            Ok(m)  => (m, None),
+            Err(e) => {
+                let raw = super::extract(&data, &path, &Options { mode: Mode::Raw, extractor: None, ..*opts });
+                (raw.with_context(|| format!("member {path}"))?, Some(format!("{e:#}")))
+            }
+        };
+        let leaf = PageLeaf::new(member_leaf_payload(&path, &member.root), Source::Member);
+        leaves.push(match error {
+            Some(e) => leaf.with_status(Status::Failed, Some(e)),
+            None    => leaf,
+        });
+        members.push(Member { index, path, doc: member });
+    }
+
+    let mut out = Extracted::new(format, opts.mode, Unit::Member, bytes.len(), leaves)?.by("archive", "1");
+    out.members = members;
+    Ok(out)
+}
+
+// Members use the upload's settings where they apply; anything without an
+// extractor (including nested archives, which are not unpacked) is chunked.
+fn extract_member(bytes: &[u8], path: &str, opts: &Options) -> Result<Extracted> {
+    let format = Format::detect_named(bytes, path);
+    let mode = match (format, opts.mode) {
+        (Format::Zip | Format::Tar | Format::Binary, _) => Mode::Raw,
+        (Format::Docx | Format::Odt | Format::Json | Format::Csv | Format::Eml, Mode::Content | Mode::Form | Mode::PagePdf) => Mode::Text,
+        (_, mode)                                       => mode,
+    };
+    super::extract(bytes, path, &registry::for_member(format, &Options { mode, ..*opts }))
+}
+
+fn zip_files(bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).context("open zip")?;
+    let mut files = Vec::new();
+    let mut unpacked = 0u64;
+
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i).context("read zip entry")?;
+        if !file.is_file() {
+            continue;
+        }
+        let path = file.name().to_owned();
+        let data = read_limited(&mut file, &mut unpacked).with_context(|| format!("unzip {path}"))?;
+        check_limits(files.len() + 1, unpacked)?;
+        files.push((path, data));
+    }
+    Ok(files)
+}
+
+fn tar_files(bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut tar = tar::Archive::new(Cursor::new(bytes));
+    let mut files = Vec::new();
+    let mut unpacked = 0u64;
+
+    for entry in tar.entries().context("read tar")? {
+        let mut entry = entry.context("read tar entry")?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path().context("tar entry path")?.to_string_lossy().into_owned();
+        let data = read_limited(&mut entry, &mut unpacked).with_context(|| format!("untar {path}"))?;
+        check_limits(files.len() + 1, unpacked)?;
+        files.push((path, data));
+    }
+    Ok(files)
+}
+
+// declared sizes can lie (zip bombs): count what is actually inflated
+fn read_limited<R: Read>(reader: &mut R, unpacked: &mut u64) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(MAX_UNPACKED - *unpacked + 1).read_to_end(&mut data)?;
+    *unpacked += data.len() as u64;
+    Ok(data)
+}
+
+fn check_limits(n_files: usize, unpacked: u64) -> Result<()> {
+    if n_files > MAX_MEMBERS {
+        bail!("archive has more than {MAX_MEMBERS} files");
+    }
+    if unpacked > MAX_UNPACKED {
+        bail!("archive unpacks to more than {MAX_UNPACKED} bytes");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::canonical::Profile;
+
+    fn tar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn opts() -> Options {
+        Options { mode: Mode::Text, profile: Profile::DEFAULT, chunk_size: 4096, metadata: false, extractor: None }
+    }
+
+    #[test]
+    fn members_sorted_by_path() {
+        let a = extract(&tar(&[("b.txt", b"two"), ("a.txt", b"one")]), Format::Tar, &opts()).unwrap();
+        let b = extract(&tar(&[("a.txt", b"one"), ("b.txt", b"two")]), Format::Tar, &opts()).unwrap();
+        assert_eq!(a.root, b.root);
+        assert_eq!(a.members.iter().map(|m| m.path.as_str()).collect::<Vec<_>>(), ["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn unreadable_member_is_chunked() {
+        let broken = b"%PDF-1.7\nnot really a PDF";
+        let ex = extract(&tar(&[("a.txt", b"one"), ("broken.pdf", broken)]), Format::Tar, &opts()).unwrap();
+        let member = &ex.members[1];
+        assert_eq!(member.doc.mode, Mode::Raw);
+        assert_eq!(ex.leaves[1].status, Status::Failed);
+        assert!(ex.leaves[1].error.is_some());
+        assert_eq!(ex.leaves[0].status, Status::Ok);
+        // the leaf still binds the path to the raw bytes' root
+        assert_eq!(ex.leaves[1].payload, member_leaf_payload("broken.pdf", &member.doc.root));
+    }
+}
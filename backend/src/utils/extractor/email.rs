@@ -0,0 +1,170 @@
+use anyhow::{Context, Result};
+use mailparse::{DispositionType, MailHeaderMap, ParsedMail};
+use serde_json::json;
+use crate::merkle::coreFunctions::generate_hash;
+use super::{member_leaf_payload, registry, Extracted, Format, Member, Mode, Options, PageLeaf, Source, Status, Unit};
+
+/// Headers committed as leaves of their own, in this order.
+const HEADERS: [&str; 5] = ["From", "To", "Date", "Subject", "Message-ID"];
+
+/// Header lines an upload must open with to pass for an e-mail by content.
+const MIN_HEADERS: usize = 2;
+
+/// True when the upload opens with at least `MIN_HEADERS` RFC 5322 header
+/// fields (`Name: value`, name without spaces, folded lines allowed), the
+/// first of them one a mail starts with.
+pub(super) fn looks_like_email(bytes: &[u8]) -> bool {
+    const FIRST: [&[u8]; 8] = [
+        b"Return-Path:", b"Received:", b"From:", b"MIME-Version:",
+        b"Message-ID:", b"Delivered-To:", b"Date:", b"X-",
+    ];
+    if !FIRST.iter().any(|h| bytes.len() >= h.len() && bytes[..h.len()].eq_ignore_ascii_case(h)) {
+        return false;
+    }
+    let mut fields = 0;
+    for line in bytes.split(|&b| b == b'\n').map(|l| l.strip_suffix(b"\r").unwrap_or(l)) {
+        if fields >= MIN_HEADERS || line.is_empty() {
+            break;
+        }
+        if line[0] == b' ' || line[0] == b'\t' {
+            continue;
+        }
+        match line.iter().position(|&b| b == b':') {
+            Some(n) if n > 0 && line[..n].iter().all(|&b| (33..=126).contains(&b)) => fields += 1,
+            _ => return false,
+        }
+    }
+    fields >= MIN_HEADERS
+}
+
+/// Leaves, each disclosable on its own:
+///   * `header:<Name>` — `["eml-header-v1", name, value]` for `HEADERS`
+///   * `part:<n>`      — `["eml-part-v1", mimetype, text]`, text parts
+///     canonicalized with the upload's profile
+///   * `attachment:<n>` — `["eml-attachment-v1", filename, sha256]`, or, for
+///     attachments we can extract (PDF, office, …), a member leaf binding
+///     the attachment's own document root so its pages chain up to here.
+pub(super) fn extract(bytes: &[u8], opts: &Options) -> Result<Extracted> {
+    let mail = mailparse::parse_mail(bytes).context("parse e-mail")?;
+    let mut leaves  = Vec::new();
+    let mut members = Vec::new();
+
+    for name in HEADERS {
+        let values = mail.headers.get_all_values(name);
+        if values.is_empty() {
+            continue;
+        }
+        let payload = json!(["eml-header-v1", name, values.join(", ")]).to_string().into_bytes();
+        leaves.push(PageLeaf::keyed(format!("header:{name}"), payload, Source::Header));
+    }
+
+    let mut n_parts = 0;
+    let mut n_attachments = 0;
+    for part in mail.parts().filter(|p| p.subparts.is_empty()) {
+        if let Some(filename) = attachment_name(part) {
+            let data = part.get_body_raw().context("decode attachment")?;
+            let key  = format!("attachment:{n_attachments}");
+            n_attachments += 1;
+
+            // one that fails to extract (encrypted, truncated) is hashed whole
+            let (doc, error) = match extract_attachment(&data, &filename, opts) {
+                Ok(doc) => (doc, None),
+                Err(e)  => (None, Some(format!("{e:#}"))),
+            };
+            if let Some(doc) = doc {
+                let payload = member_leaf_payload(&filename, &doc.root);
+                members.push(Member { index: leaves.len(), path: filename, doc });
+                leaves.push(PageLeaf::keyed(key, payload, Source::Attachment));
+            } else {
+                let payload = json!(["eml-attachment-v1", filename, generate_hash(&data)])
+                    .to_string()
+                    .into_bytes();
+                let leaf = PageLeaf::keyed(key, payload, Source::Attachment);
+                leaves.push(match error {
+                    Some(e) => leaf.with_status(Status::Failed, Some(e)),
+                    None    => leaf,
+                });
+            }
+        } else if part.ctype.mimetype.starts_with("text/") {
+            let text = opts.profile.apply(&part.get_body().context("decode text part")?);
+            let payload = json!(["eml-part-v1", part.ctype.mimetype, text]).to_string().into_bytes();
+            leaves.push(PageLeaf::keyed(format!("part:{n_parts}"), payload, Source::Part));
+            n_parts += 1;
+        }
+    }
+
+    let mut out = Extracted::new(Format::Eml, opts.mode, Unit::Part, bytes.len(), leaves)?.by("eml", "1");
+    out.members = members;
+    Ok(out)
+}
+
+// Attachments are parts marked as such or carrying a file name; inline
+// images without one still count, under an empty name.
+fn attachment_name(part: &ParsedMail) -> Option<String> {
+    let disposition = part.get_content_disposition();
+    let filename = disposition
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"))
+        .cloned();
+    match (disposition.disposition, filename) {
+        (_, Some(name))                 => Some(name),
+        (DispositionType::Attachment, _) => Some(String::new()),
+        _ if !part.ctype.mimetype.starts_with("text/") => Some(String::new()),
+        _ => None,
+    }
+}
+
+// Attachments in a format we extract become a subtree; e-mails and
+// archives inside e-mails are not unpacked further.
+fn extract_attachment(data: &[u8], filename: &str, opts: &Options) -> Result<Option<Extracted>> {
+    let format = Format::detect_named(data, filename);
+    let mode = match (format, opts.mode) {
+        (Format::Binary | Format::Eml | Format::Zip | Format::Tar, _) => return Ok(None),
+        (Format::Pdf, mode) => mode,
+        _ => Mode::Text,
+    };
+    super::extract(data, filename, &registry::for_member(format, &Options { mode, ..*opts }))
+        .with_context(|| format!("attachment {filename}"))
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use crate::utils::canonical::Profile;
+
+    #[test]
+    fn mail_needs_two_header_lines() {
+        assert!(looks_like_email(b"From: a@example.org\r\nTo: b@example.org\r\n\r\nhi"));
+        assert!(looks_like_email(b"Received: from x\n\tby y\nDate: Mon, 1 Jan 2024 00:00:00 +0000\n"));
+        // one header, then a body
+        assert!(!looks_like_email(b"From: a@example.org\n\nhi"));
+        assert!(!looks_like_email(b"Date: 2024-01-01\n"));
+        // logs and CSVs that happen to start alike
+        assert!(!looks_like_email(b"Date: 2024-01-01 12:00:00 started\n2024-01-01 12:00:01 ERROR: x\n"));
+        assert!(!looks_like_email(b"X-coordinate,Y-coordinate\n1,2\n"));
+        assert!(!looks_like_email(b"Subject: hi\nFrom: a@example.org\n"));
+    }
+
+    #[test]
+    fn unreadable_attachment_is_hashed() {
+        let pdf = b"%PDF-1.4\ntruncated";
+        let mail = format!(
+            "From: a@example.org\r\nTo: b@example.org\r\nMIME-Version: 1.0\r\n\
+             Content-Type: multipart/mixed; boundary=XX\r\n\r\n\
+             --XX\r\nContent-Type: text/plain\r\n\r\nsee attached\r\n\
+             --XX\r\nContent-Type: application/pdf\r\n\
+             Content-Transfer-Encoding: base64\r\n\
+             Content-Disposition: attachment; filename=\"deal.pdf\"\r\n\r\n{}\r\n--XX--\r\n",
+            base64::engine::general_purpose::STANDARD.encode(pdf),
+        );
+        let opts = Options { mode: Mode::Text, profile: Profile::DEFAULT, chunk_size: 4096, metadata: false, extractor: None };
+        let ex = extract(mail.as_bytes(), &opts).unwrap();
+        assert!(ex.members.is_empty());
+        let leaf = ex.leaves.iter().find(|l| l.source == Source::Attachment).unwrap();
+        assert_eq!(leaf.status, Status::Failed);
+        assert_eq!(leaf.payload, json!(["eml-attachment-v1", "deal.pdf", generate_hash(pdf)]).to_string().into_bytes());
+    }
+}
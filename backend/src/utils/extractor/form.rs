@@ -0,0 +1,140 @@
+use anyhow::{bail, Context, Result};
+use lopdf::{Dictionary, Document, Object};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use super::metadata::decode_text_string;
+use super::registry::LOPDF;
+use super::{member_leaf_payload, pdf_to_content_pages, Extracted, Format, Member, Mode, Options, PageLeaf, Source, Unit};
+
+/// Key and member path of the template subtree.
+pub const TEMPLATE_KEY: &str = "template";
+
+// /Kids chains deeper than this are treated as malformed
+const MAX_DEPTH: usize = 32;
+
+/// A filled-in PDF form as two parts under one root:
+///   * leaf 0, `template` — a member leaf binding the root of the pages in
+///     content mode. Field values live in widget annotations, not in page
+///     content, so a blank copy of the form uploaded with `mode=content`
+///     has that same root.
+///   * `field:<name>` — `["pdf-field-v1", name, type, value]` per terminal
+///     AcroForm field, by fully qualified name in sorted order. Values are
+///     committed verbatim: text, the chosen state of a button, or a list of
+///     choices. Signature fields are left out; they are covered by the
+///     signature report.
+pub(super) fn extract(bytes: &[u8], opts: &Options) -> Result<Extracted> {
+    if let Some(name) = opts.extractor.filter(|&name| name != LOPDF.name()) {
+        bail!("extractor {name} doesn't handle form mode; it is built on {}", LOPDF.name());
+    }
+    let template = Extracted::new(Format::Pdf, Mode::Content, Unit::Page, bytes.len(), pdf_to_content_pages(bytes)?)?
+        .by(LOPDF.name(), LOPDF.version());
+
+    let doc = Document::load_mem(bytes).context("parse PDF")?;
+    let mut fields = BTreeMap::new();
+    let roots = doc
+        .catalog()
+        .ok()
+        .and_then(|cat| cat.get(b"AcroForm").ok())
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_dict().ok())
+        .and_then(|form| form.get(b"Fields").ok())
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_array().ok());
+    for field in roots.into_iter().flatten() {
+        walk(&doc, field, &Inherited::default(), 0, &mut fields);
+    }
+
+    let mut leaves = vec![PageLeaf::keyed(
+        TEMPLATE_KEY.to_owned(),
+        member_leaf_payload(TEMPLATE_KEY, &template.root),
+        Source::Member,
+    )];
+    for (name, (kind, value)) in fields {
+        let payload = json!(["pdf-field-v1", name, kind, value]).to_string().into_bytes();
+        leaves.push(PageLeaf::keyed(format!("field:{name}"), payload, Source::Field));
+    }
+
+    let mut out = super::pdf_document(leaves, Mode::Form, Unit::Field, bytes, opts)?
+        .by(LOPDF.name(), LOPDF.version());
+    out.members = vec![Member { index: 0, path: TEMPLATE_KEY.to_owned(), doc: template }];
+    Ok(out)
+}
+
+// name, /FT and /V are inherited from parent fields
+#[derive(Default, Clone)]
+struct Inherited {
+    name:  String,
+    kind:  Option<Vec<u8>>,
+    value: Option<Value>,
+}
+
+fn walk(doc: &Document, obj: &Object, parent: &Inherited, depth: usize, out: &mut BTreeMap<String, (String, Value)>) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+    let Some(dict) = doc.dereference(obj).ok().and_then(|(_, o)| o.as_dict().ok()) else {
+        return;
+    };
+
+    let mut here = parent.clone();
+    if let Ok(Object::String(t, _)) = dict.get(b"T") {
+        let partial = decode_text_string(t);
+        here.name = if parent.name.is_empty() { partial } else { format!("{}.{partial}", parent.name) };
+    }
+    if let Ok(Object::Name(kind)) = dict.get(b"FT") {
+        here.kind = Some(kind.clone());
+    }
+    if let Some(value) = field_value(doc, dict) {
+        here.value = Some(value);
+    }
+
+    // kids without /T are widget annotations of this field, not fields
+    let kids: Vec<&Object> = dict
+        .get(b"Kids")
+        .ok()
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_array().ok())
+        .map(|a| a.iter().collect())
+        .unwrap_or_default();
+    let child_fields: Vec<&Object> = kids
+        .into_iter()
+        .filter(|k| {
+            doc.dereference(k)
+                .ok()
+                .and_then(|(_, o)| o.as_dict().ok())
+                .is_some_and(|d| d.has(b"T"))
+        })
+        .collect();
+
+    if !child_fields.is_empty() {
+        for kid in child_fields {
+            walk(doc, kid, &here, depth + 1, out);
+        }
+        return;
+    }
+    let kind = here.kind.as_deref().map(|k| String::from_utf8_lossy(k).into_owned()).unwrap_or_default();
+    if kind == "Sig" || here.name.is_empty() {
+        return;
+    }
+    // duplicate names are malformed; the first one in /Fields order wins
+    out.entry(here.name).or_insert((kind, here.value.unwrap_or(Value::Null)));
+}
+
+fn field_value(doc: &Document, dict: &Dictionary) -> Option<Value> {
+    let value = doc.dereference(dict.get(b"V").ok()?).ok()?.1;
+    match value {
+        Object::String(s, _) => Some(Value::String(decode_text_string(s))),
+        Object::Name(n)      => Some(Value::String(String::from_utf8_lossy(n).into_owned())),
+        Object::Array(items) => Some(Value::Array(
+            items
+                .iter()
+                .filter_map(|o| match o {
+                    Object::String(s, _) => Some(Value::String(decode_text_string(s))),
+                    Object::Name(n)      => Some(Value::String(String::from_utf8_lossy(n).into_owned())),
+                    _ => None,
+                })
+                .collect(),
+        )),
+        _ => None,
+    }
+}
@@ -0,0 +1,194 @@
+use anyhow::{Context, Result};
+use lopdf::{Dictionary, Document, Object};
+use super::metadata::decode_text_string;
+
+// /Kids chains deeper than this are treated as malformed
+const MAX_DEPTH: usize = 32;
+// /St and the numbers counted from it; past this a range is malformed
+const MAX_NUMBER: usize = 1_000_000;
+// longer labels (long prefixes, "ZZZ…") fall back to the page number
+const MAX_LABEL_LEN: usize = 64;
+
+/// The printed label of every page ("iv", "A-3", …) from the catalog's
+/// `/PageLabels` number tree. Without one, pages are labelled 1, 2, 3, …
+/// like viewers do.
+pub fn pdf_page_labels(bytes: &[u8]) -> Result<Vec<String>> {
+    let doc = Document::load_mem(bytes).context("parse PDF")?;
+    let n_pages = doc.get_pages().len();
+
+    let mut ranges: Vec<(usize, &Dictionary)> = Vec::new();
+    if let Some(tree) = doc
+        .catalog()
+        .ok()
+        .and_then(|cat| cat.get(b"PageLabels").ok())
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_dict().ok())
+    {
+        collect_ranges(&doc, tree, 0, &mut ranges);
+    }
+    ranges.sort_by_key(|(start, _)| *start);
+
+    let mut labels = Vec::with_capacity(n_pages);
+    for page in 0..n_pages {
+        // the range that starts at or before this page, if any
+        let label = match ranges.iter().rev().find(|(start, _)| *start <= page) {
+            Some((start, style)) => range_label(style, page - start).unwrap_or_else(|| (page + 1).to_string()),
+            None                 => (page + 1).to_string(),
+        };
+        labels.push(label);
+    }
+    Ok(labels)
+}
+
+fn collect_ranges<'a>(doc: &'a Document, node: &'a Dictionary, depth: usize, out: &mut Vec<(usize, &'a Dictionary)>) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+    if let Ok(Object::Array(nums)) = node.get(b"Nums").and_then(|o| doc.dereference(o)).map(|(_, o)| o) {
+        for pair in nums.chunks_exact(2) {
+            let start = pair[0].as_i64().ok().and_then(|n| usize::try_from(n).ok());
+            let style = doc.dereference(&pair[1]).ok().and_then(|(_, o)| o.as_dict().ok());
+            if let (Some(start), Some(style)) = (start, style) {
+                out.push((start, style));
+            }
+        }
+    }
+    if let Ok(Object::Array(kids)) = node.get(b"Kids").and_then(|o| doc.dereference(o)).map(|(_, o)| o) {
+        for kid in kids {
+            if let Some(kid) = doc.dereference(kid).ok().and_then(|(_, o)| o.as_dict().ok()) {
+                collect_ranges(doc, kid, depth + 1, out);
+            }
+        }
+    }
+}
+
+// /P prefix + the number in style /S, counting from /St (default 1); a
+// range without /S has only the prefix. `None` when /St is out of range
+// or the label would be unreasonably long.
+fn range_label(style: &Dictionary, offset: usize) -> Option<String> {
+    let prefix = match style.get(b"P") {
+        Ok(Object::String(p, _)) => decode_text_string(p),
+        _ => String::new(),
+    };
+    let first = style.get(b"St").and_then(Object::as_i64).unwrap_or(1).max(1);
+    let n = usize::try_from(first).ok()?.checked_add(offset).filter(|&n| n <= MAX_NUMBER)?;
+    let number = match style.get(b"S") {
+        Ok(Object::Name(s)) => match s.as_slice() {
+            b"D" => n.to_string(),
+            b"R" => roman(n)?,
+            b"r" => roman(n)?.to_lowercase(),
+            b"A" => letters(n)?,
+            b"a" => letters(n)?.to_lowercase(),
+            _    => String::new(),
+        },
+        _ => String::new(),
+    };
+    let label = format!("{prefix}{number}");
+    (label.chars().count() <= MAX_LABEL_LEN).then_some(label)
+}
+
+fn roman(mut n: usize) -> Option<String> {
+    // one "M" per thousand
+    if n / 1000 > MAX_LABEL_LEN {
+        return None;
+    }
+    const DIGITS: [(usize, &str); 13] = [
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+        (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+    let mut out = String::new();
+    for (value, digit) in DIGITS {
+        while n >= value {
+            out.push_str(digit);
+            n -= value;
+        }
+    }
+    Some(out)
+}
+
+// A‥Z, then AA‥ZZ, AAA‥ — the letter repeated, per the PDF spec
+fn letters(n: usize) -> Option<String> {
+    let count = n.checked_sub(1)? / 26 + 1;
+    if count > MAX_LABEL_LEN {
+        return None;
+    }
+    let letter = (b'A' + ((n - 1) % 26) as u8) as char;
+    Some(letter.to_string().repeat(count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(entries: Vec<(&str, Object)>) -> Dictionary {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.set(key, value);
+        }
+        dict
+    }
+
+    #[test]
+    fn roman_numerals() {
+        assert_eq!(roman(0).as_deref(), Some(""));
+        assert_eq!(roman(1).as_deref(), Some("I"));
+        assert_eq!(roman(4).as_deref(), Some("IV"));
+        assert_eq!(roman(9).as_deref(), Some("IX"));
+        assert_eq!(roman(1994).as_deref(), Some("MCMXCIV"));
+        assert_eq!(roman(3999).as_deref(), Some("MMMCMXCIX"));
+        assert_eq!(roman(MAX_LABEL_LEN * 1000).map(|r| r.len()), Some(MAX_LABEL_LEN));
+        assert_eq!(roman((MAX_LABEL_LEN + 1) * 1000), None);
+        assert_eq!(roman(usize::MAX), None);
+    }
+
+    #[test]
+    fn letter_labels() {
+        assert_eq!(letters(0), None);
+        assert_eq!(letters(1).as_deref(), Some("A"));
+        assert_eq!(letters(26).as_deref(), Some("Z"));
+        assert_eq!(letters(27).as_deref(), Some("AA"));
+        assert_eq!(letters(52).as_deref(), Some("ZZ"));
+        assert_eq!(letters(53).as_deref(), Some("AAA"));
+        assert_eq!(letters(26 * MAX_LABEL_LEN).map(|l| l.len()), Some(MAX_LABEL_LEN));
+        assert_eq!(letters(26 * MAX_LABEL_LEN + 1), None);
+        assert_eq!(letters(usize::MAX), None);
+    }
+
+    #[test]
+    fn range_labels() {
+        let decimal = style(vec![("S", Object::Name(b"D".to_vec()))]);
+        assert_eq!(range_label(&decimal, 0).as_deref(), Some("1"));
+        assert_eq!(range_label(&decimal, 4).as_deref(), Some("5"));
+
+        let appendix = style(vec![
+            ("S", Object::Name(b"D".to_vec())),
+            ("P", Object::string_literal("A-")),
+            ("St", Object::Integer(3)),
+        ]);
+        assert_eq!(range_label(&appendix, 0).as_deref(), Some("A-3"));
+        assert_eq!(range_label(&appendix, 2).as_deref(), Some("A-5"));
+
+        let lower = style(vec![("S", Object::Name(b"r".to_vec()))]);
+        assert_eq!(range_label(&lower, 3).as_deref(), Some("iv"));
+        let prefix_only = style(vec![("P", Object::string_literal("Cover"))]);
+        assert_eq!(range_label(&prefix_only, 7).as_deref(), Some("Cover"));
+        let negative = style(vec![("S", Object::Name(b"D".to_vec())), ("St", Object::Integer(-5))]);
+        assert_eq!(range_label(&negative, 0).as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn range_label_bounds() {
+        let at = |st: i64, s: &[u8]| style(vec![("S", Object::Name(s.to_vec())), ("St", Object::Integer(st))]);
+        let max = MAX_NUMBER as i64;
+        assert_eq!(range_label(&at(max, b"D"), 0), Some(MAX_NUMBER.to_string()));
+        assert_eq!(range_label(&at(max, b"D"), 1), None);
+        assert_eq!(range_label(&at(max + 1, b"D"), 0), None);
+        assert_eq!(range_label(&at(i64::MAX, b"R"), 0), None);
+        assert_eq!(range_label(&at(i64::MAX, b"A"), 0), None);
+        assert_eq!(range_label(&at(1, b"D"), usize::MAX), None);
+        // within range, but too long to print
+        assert_eq!(range_label(&at(max, b"R"), 0), None);
+        let long = style(vec![("P", Object::string_literal("x".repeat(MAX_LABEL_LEN + 1)))]);
+        assert_eq!(range_label(&long, 0), None);
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use lopdf::{Document, Object};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::BTreeMap;
+use unicode_normalization::UnicodeNormalization;
+use super::{PageLeaf, Source};
+
+/// Info keys that describe authorship. `Producer` and `ModDate` are left
+/// out on purpose: every tool that re-saves the file rewrites them.
+const INFO_KEYS: [&str; 6] = ["Title", "Author", "Subject", "Keywords", "Creator", "CreationDate"];
+
+/// XMP properties with the same meaning as `INFO_KEYS`.
+const XMP_PROPS: [&str; 6] = [
+    "dc:title", "dc:creator", "dc:description", "pdf:Keywords", "xmp:CreatorTool", "xmp:CreateDate",
+];
+
+/// The document Info dictionary and XMP packet reduced to a canonical
+/// record, committed as one leaf keyed `metadata`:
+/// `["pdf-meta-v1", {"info": {…}, "xmp": {…}}]` with sorted keys, NFC
+/// text and PDF dates rewritten as ISO 8601.
+pub fn pdf_metadata_leaf(bytes: &[u8]) -> Result<PageLeaf> {
+    let doc = Document::load_mem(bytes).context("parse PDF")?;
+
+    let mut info = BTreeMap::new();
+    if let Ok((_, Object::Dictionary(dict))) = doc.trailer.get(b"Info").and_then(|o| doc.dereference(o)) {
+        for key in INFO_KEYS {
+            if let Ok(Object::String(raw, _)) = dict.get(key.as_bytes()).and_then(|o| doc.dereference(o)).map(|(_, o)| o) {
+                let text = decode_text_string(raw);
+                let value = if key.ends_with("Date") { pdf_date_to_iso(&text).unwrap_or(text) } else { text };
+                if !value.is_empty() {
+                    info.insert(key.to_owned(), value);
+                }
+            }
+        }
+    }
+
+    let xmp = doc
+        .catalog()
+        .ok()
+        .and_then(|cat| cat.get(b"Metadata").ok())
+        .and_then(|o| doc.dereference(o).ok())
+        .and_then(|(_, o)| o.as_stream().ok())
+        .map(|s| s.decompressed_content().unwrap_or_else(|_| s.content.clone()))
+        .map(|packet| xmp_properties(&packet))
+        .unwrap_or_default();
+
+    let record = ("pdf-meta-v1", BTreeMap::from([("info", info), ("xmp", xmp)]));
+    let payload = serde_json::to_vec(&record)?;
+    Ok(PageLeaf::keyed("metadata".to_owned(), payload, Source::Metadata))
+}
+
+// Text of each wanted property, whether written as an element (with
+// rdf:Alt / rdf:Seq / rdf:Bag items joined by "; ") or as an attribute of
+// rdf:Description. A malformed packet yields whatever parsed before it.
+fn xmp_properties(packet: &[u8]) -> BTreeMap<String, String> {
+    let mut out: BTreeMap<String, String> = BTreeMap::new();
+    let mut reader = Reader::from_reader(packet);
+    let mut buf = Vec::new();
+    let mut current: Option<(String, Vec<String>)> = None;
+    let mut depth = 0usize;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                if current.is_some() {
+                    depth += 1;
+                } else if XMP_PROPS.contains(&name.as_str()) {
+                    current = Some((name, Vec::new()));
+                    depth = 0;
+                } else if name == "rdf:Description" {
+                    for attr in e.attributes().flatten() {
+                        let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+                        if XMP_PROPS.contains(&key.as_str()) {
+                            if let Ok(v) = attr.unescape_value() {
+                                out.insert(key, clean(&v));
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(Event::Text(t)) => {
+                if let (Some((_, parts)), Ok(text)) = (&mut current, t.unescape()) {
+                    if !text.trim().is_empty() {
+                        parts.push(clean(&text));
+                    }
+                }
+            }
+            Ok(Event::End(_)) => {
+                if current.is_some() && depth > 0 {
+                    depth -= 1;
+                } else if let Some((name, parts)) = current.take() {
+                    out.insert(name, parts.join("; "));
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    out
+}
+
+fn clean(text: &str) -> String {
+    text.trim().nfc().collect()
+}
+
+// PDF text strings are UTF-16BE with a BOM, or PDFDocEncoding — which
+// matches Latin-1 for everything that shows up in real metadata
+pub(super) fn decode_text_string(raw: &[u8]) -> String {
+    let text = if let Some(utf16) = raw.strip_prefix(b"\xFE\xFF") {
+        let units: Vec<u16> = utf16.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+        String::from_utf16_lossy(&units)
+    } else if let Some(utf8) = raw.strip_prefix(b"\xEF\xBB\xBF") {
+        String::from_utf8_lossy(utf8).into_owned()
+    } else {
+        raw.iter().map(|&b| b as char).collect()
+    };
+    clean(&text)
+}
+
+// "D:YYYYMMDDHHmmSSOHH'mm'" with every part after the year optional
+pub(super) fn pdf_date_to_iso(date: &str) -> Option<String> {
+    let d = date.strip_prefix("D:").unwrap_or(date);
+    let digits: String = d.chars().take_while(|c| c.is_ascii_digit()).collect();
+    if digits.len() < 4 {
+        return None;
+    }
+    let field = |from: usize, len: usize, default: &str| -> String {
+        digits.get(from..from + len).map_or(default.to_owned(), str::to_owned)
+    };
+    let mut iso = format!(
+        "{}-{}-{}T{}:{}:{}",
+        field(0, 4, "0000"), field(4, 2, "01"), field(6, 2, "01"),
+        field(8, 2, "00"), field(10, 2, "00"), field(12, 2, "00"),
+    );
+
+    let tz: Vec<char> = d[digits.len()..].chars().filter(|c| *c != '\'').collect();
+    match tz.first() {
+        Some('Z') | None => iso.push('Z'),
+        Some(sign @ ('+' | '-')) => {
+            let rest: String = tz[1..].iter().collect();
+            let hh = rest.get(0..2).unwrap_or("00");
+            let mm = rest.get(2..4).unwrap_or("00");
+            iso.push_str(&format!("{sign}{hh}:{mm}"));
+        }
+        Some(_) => return None,
+    }
+    Some(iso)
+}
@@ -0,0 +1,416 @@
+mod archive;
+mod email;
+mod form;
+mod labels;
+mod metadata;
+mod office;
+mod pdf;
+mod raw;
+mod registry;
+mod signature;
+mod split;
+mod structured;
+
+use anyhow::{bail, Result};
+use std::io::Cursor;
+use crate::merkle::coreFunctions::{build_tree_from_hashes, generate_hash};
+use crate::utils::canonical::Profile;
+use crate::utils::stamp::original_len;
+
+pub use archive::member_leaf_payload;
+pub use form::TEMPLATE_KEY;
+pub use labels::pdf_page_labels;
+pub use metadata::pdf_metadata_leaf;
+pub use office::{docx_to_pages, odt_to_pages};
+pub use pdf::{decrypt_pdf, needs_password, pdf_extract_pages, pdf_to_content_pages, pdf_to_pages};
+pub use raw::{chunk_leaves, DEFAULT_CHUNK_SIZE, MAX_CHUNK_SIZE};
+pub use registry::{by_name as extractor_by_name, PageExtractor, EXTRACTORS};
+pub use split::{pdf_page_files, pdf_single_page};
+pub use signature::{pdf_signatures, signatures_leaf, Signature};
+pub use structured::{csv_to_leaves, json_to_leaves};
+
+/// What a page leaf is computed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// extracted text, canonicalized with a `canonical::Profile`
+    Text,
+    /// decoded content streams + referenced XObjects and fonts
+    Content,
+    /// fixed-size byte chunks of the file, whatever its format
+    Raw,
+    /// PDF forms: the template pages as a subtree plus one leaf per field
+    Form,
+    /// each page as a standalone one-page PDF, servable next to its proof
+    PagePdf,
+}
+
+impl Mode {
+    pub fn id(self) -> &'static str {
+        match self {
+            Mode::Text    => "text",
+            Mode::Content => "content",
+            Mode::Raw     => "raw",
+            Mode::Form    => "form",
+            Mode::PagePdf => "page-pdf",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Mode> {
+        match id {
+            "text"    => Some(Mode::Text),
+            "content" => Some(Mode::Content),
+            "raw"     => Some(Mode::Raw),
+            "form"    => Some(Mode::Form),
+            "page-pdf" => Some(Mode::PagePdf),
+            _         => None,
+        }
+    }
+}
+
+/// Where a page leaf's payload came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Text,
+    /// no text layer: hashes of the raster images drawn on the page
+    Images,
+    Content,
+    Chunk,
+    /// archive member: its path bound to its own document root
+    Member,
+    /// JSON scalar at a JSON Pointer, or PDF form field
+    Field,
+    /// CSV data row with its header
+    Row,
+    /// selected e-mail header
+    Header,
+    /// e-mail text body part
+    Part,
+    /// e-mail attachment: its hash, or a member leaf for extracted ones
+    Attachment,
+    /// canonical PDF Info + XMP record, after the last page
+    Metadata,
+    /// report of the PDF's embedded signatures, after the last page
+    Signatures,
+    /// one-page PDF file of the page
+    PagePdf,
+}
+
+impl Source {
+    pub fn id(self) -> &'static str {
+        match self {
+            Source::Text    => "text",
+            Source::Images  => "images",
+            Source::Content => "content",
+            Source::Chunk   => "chunk",
+            Source::Member  => "member",
+            Source::Field   => "field",
+            Source::Row     => "row",
+            Source::Header  => "header",
+            Source::Part    => "part",
+            Source::Attachment => "attachment",
+            Source::Metadata   => "metadata",
+            Source::Signatures => "signatures",
+            Source::PagePdf    => "page-pdf",
+        }
+    }
+}
+
+/// How extraction went for one leaf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// no text and nothing else drawn: the content stream was hashed
+    Empty,
+    /// extraction failed: the page's content stream, an archive member's
+    /// raw bytes or an e-mail attachment's hash were committed instead
+    Failed,
+    /// no text: hashed from the page's images instead
+    FallbackUsed,
+}
+
+impl Status {
+    pub fn id(self) -> &'static str {
+        match self {
+            Status::Ok           => "ok",
+            Status::Empty        => "empty",
+            Status::Failed       => "failed",
+            Status::FallbackUsed => "fallback-used",
+        }
+    }
+}
+
+/// Bytes that get hashed into a page leaf.
+pub struct PageLeaf {
+    pub payload: Vec<u8>,
+    /// digest of a payload that isn't kept (raw chunks, hashed straight
+    /// from the upload), with `payload` left empty
+    pub hash:    Option<String>,
+    pub source:  Source,
+    /// addressable name of a disclosable leaf (JSON Pointer, CSV row)
+    pub key:     Option<String>,
+    pub status:  Status,
+    /// why extraction failed, for `Status::Failed`
+    pub error:   Option<String>,
+}
+
+impl PageLeaf {
+    pub fn new(payload: Vec<u8>, source: Source) -> Self {
+        PageLeaf { payload, hash: None, source, key: None, status: Status::Ok, error: None }
+    }
+
+    pub fn keyed(key: String, payload: Vec<u8>, source: Source) -> Self {
+        PageLeaf { payload, hash: None, source, key: Some(key), status: Status::Ok, error: None }
+    }
+
+    pub fn hashed(payload: &[u8], source: Source) -> Self {
+        PageLeaf { hash: Some(generate_hash(payload)), ..PageLeaf::new(Vec::new(), source) }
+    }
+
+    pub fn with_status(mut self, status: Status, error: Option<String>) -> Self {
+        self.status = status;
+        self.error  = error;
+        self
+    }
+}
+
+/// What one leaf of a document stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Page,
+    /// office documents without explicit page breaks
+    Paragraph,
+    /// fixed-size byte range, raw mode
+    Chunk,
+    /// one file of a ZIP/TAR archive
+    Member,
+    /// one JSON value, addressed by JSON Pointer, or one PDF form field
+    Field,
+    /// one CSV data row
+    Row,
+    /// one e-mail header, body part or attachment
+    Part,
+}
+
+impl Unit {
+    pub fn id(self) -> &'static str {
+        match self {
+            Unit::Page      => "page",
+            Unit::Paragraph => "paragraph",
+            Unit::Chunk     => "chunk",
+            Unit::Member    => "member",
+            Unit::Field     => "field",
+            Unit::Row       => "row",
+            Unit::Part      => "part",
+        }
+    }
+}
+
+/// Container format of an upload, detected from its leading bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Pdf,
+    Docx,
+    Odt,
+    Zip,
+    Tar,
+    Json,
+    Csv,
+    Eml,
+    /// anything else — only accepted in raw mode
+    Binary,
+}
+
+impl Format {
+    pub fn id(self) -> &'static str {
+        match self {
+            Format::Pdf  => "pdf",
+            Format::Docx => "docx",
+            Format::Odt  => "odt",
+            Format::Zip  => "zip",
+            Format::Tar  => "tar",
+            Format::Json => "json",
+            Format::Csv  => "csv",
+            Format::Eml  => "eml",
+            Format::Binary => "binary",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Format> {
+        match id {
+            "pdf"    => Some(Format::Pdf),
+            "docx"   => Some(Format::Docx),
+            "odt"    => Some(Format::Odt),
+            "zip"    => Some(Format::Zip),
+            "tar"    => Some(Format::Tar),
+            "json"   => Some(Format::Json),
+            "csv"    => Some(Format::Csv),
+            "eml"    => Some(Format::Eml),
+            "binary" => Some(Format::Binary),
+            _        => None,
+        }
+    }
+
+    pub fn detect(bytes: &[u8]) -> Format {
+        // fixed-offset magics first: a stored archive member can put a
+        // PDF header into the first KiB of the container
+        if bytes.starts_with(b"PK\x03\x04") {
+            return zip::ZipArchive::new(Cursor::new(bytes))
+                .ok()
+                .and_then(office::detect)
+                .unwrap_or(Format::Zip);
+        }
+        if bytes.get(257..262) == Some(b"ustar") {
+            return Format::Tar;
+        }
+        // PDF allows junk before the header; readers scan the first 1 KiB
+        let head = &bytes[..bytes.len().min(1024)];
+        if head.windows(5).any(|w| w == b"%PDF-") {
+            return Format::Pdf;
+        }
+        if structured::looks_like_json(bytes) {
+            return Format::Json;
+        }
+        Format::Binary
+    }
+
+    /// `detect`, plus the file name and header sniffing for formats
+    /// without a signature (CSV, e-mail, JSON whose top level is a
+    /// scalar). Uploads sent as `message/rfc822` are detected under a
+    /// `.eml` name, see `documents::Upload`.
+    pub fn detect_named(bytes: &[u8], name: &str) -> Format {
+        let name = name.to_ascii_lowercase();
+        match Format::detect(bytes) {
+            Format::Binary if name.ends_with(".json") => Format::Json,
+            Format::Binary if name.ends_with(".csv") => Format::Csv,
+            Format::Binary if name.ends_with(".eml") || email::looks_like_email(bytes) => Format::Eml,
+            f => f,
+        }
+    }
+}
+
+/// Upload settings that decide how leaves are derived.
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    pub mode:       Mode,
+    pub profile:    Profile,
+    pub chunk_size: usize,
+    /// PDFs: commit Info/XMP metadata as an extra leaf after the pages
+    pub metadata:   bool,
+    /// engine name from `EXTRACTORS`, for the files it handles
+    pub extractor:  Option<&'static str>,
+}
+
+/// A file turned into leaves. Containers (archives, e-mails) additionally
+/// carry one `Extracted` per embedded file whose root one of their leaves
+/// commits to.
+pub struct Extracted {
+    pub format:  Format,
+    /// mode actually used, e.g. `Raw` for binary archive members
+    pub mode:    Mode,
+    pub unit:    Unit,
+    pub size:    u64,
+    pub leaves:  Vec<PageLeaf>,
+    pub hashes:  Vec<String>,
+    pub root:    String,
+    pub members: Vec<Member>,
+    /// PDFs: embedded signatures, also committed as the `signatures` leaf
+    pub signatures: Vec<Signature>,
+    /// PDFs: a stamp (`utils::stamp`) was appended after the uploaded
+    /// bytes; leaves and signatures are read as if it weren't there
+    pub stamped:    bool,
+    /// PDFs: printed label of each page leaf, in page order
+    pub labels:     Vec<String>,
+    /// engine that produced the leaves, see `PageExtractor`
+    pub extractor:  &'static str,
+    pub extractor_version: &'static str,
+}
+
+/// An embedded file committed to by leaf `index` of its container via
+/// `member_leaf_payload(path, doc.root)`.
+pub struct Member {
+    pub index: usize,
+    pub path:  String,
+    pub doc:   Extracted,
+}
+
+impl Extracted {
+    fn new(format: Format, mode: Mode, unit: Unit, size: usize, leaves: Vec<PageLeaf>) -> Result<Self> {
+        if leaves.is_empty() {
+            bail!("no pages extracted");
+        }
+        let hashes: Vec<String> = leaves
+            .iter()
+            .map(|l| l.hash.clone().unwrap_or_else(|| generate_hash(&l.payload)))
+            .collect();
+        let root = build_tree_from_hashes(hashes.clone()).hash;
+        Ok(Extracted {
+            format, mode, unit, size: size as u64, leaves, hashes, root,
+            members: Vec::new(), signatures: Vec::new(), stamped: false, labels: Vec::new(),
+            extractor: "", extractor_version: "",
+        })
+    }
+
+    /// Leaves that are pages (or rows, chunks, …), not the document-level
+    /// metadata and signatures leaves appended after them.
+    pub fn n_pages(&self) -> usize {
+        self.leaves
+            .iter()
+            .filter(|l| !matches!(l.source, Source::Metadata | Source::Signatures))
+            .count()
+    }
+
+    fn by(mut self, name: &'static str, version: &'static str) -> Self {
+        self.extractor = name;
+        self.extractor_version = version;
+        self
+    }
+}
+
+/// Detects the format of an upload and derives its leaves.
+pub fn extract(bytes: &[u8], name: &str, opts: &Options) -> Result<Extracted> {
+    let format = Format::detect_named(bytes, name);
+    match (format, opts.mode) {
+        (_, Mode::Raw) => {}
+        (Format::Zip | Format::Tar, _) => return archive::extract(bytes, format, opts),
+        (Format::Eml, _) => return email::extract(bytes, opts),
+        (Format::Binary, _) => bail!("unsupported file format (use mode=raw)"),
+        (Format::Pdf, Mode::Form) => return form::extract(bytes, opts),
+        (_, Mode::Form) => bail!("form mode is only available for PDF"),
+        (Format::Pdf, _) => {}
+        (_, Mode::Content | Mode::PagePdf) => bail!("{} mode is only available for PDF", opts.mode.id()),
+        _ => {}
+    }
+
+    let engine = registry::select(format, opts)?;
+    let (leaves, unit) = engine.pages(bytes, opts)?;
+    let out = match (format, opts.mode) {
+        (_, Mode::Raw)  => Extracted::new(format, opts.mode, unit, bytes.len(), leaves)?,
+        (Format::Pdf, _) => pdf_document(leaves, opts.mode, unit, bytes, opts)?,
+        _               => Extracted::new(format, opts.mode, unit, bytes.len(), leaves)?,
+    };
+    Ok(out.by(engine.name(), engine.version()))
+}
+
+// Document-level PDF leaves go after the pages, so page indices don't
+// move: the signature report when the file is signed, then the metadata
+// record when asked for.
+fn pdf_document(mut leaves: Vec<PageLeaf>, mode: Mode, unit: Unit, bytes: &[u8], opts: &Options) -> Result<Extracted> {
+    if leaves.is_empty() {
+        bail!("no pages extracted");
+    }
+    let end = original_len(bytes);
+    let signatures = pdf_signatures(bytes, end)?;
+    if !signatures.is_empty() {
+        leaves.push(signatures_leaf(&signatures));
+    }
+    if opts.metadata {
+        leaves.push(pdf_metadata_leaf(bytes)?);
+    }
+    let mut out = Extracted::new(Format::Pdf, mode, unit, bytes.len(), leaves)?;
+    out.signatures = signatures;
+    out.stamped = end < bytes.len();
+    if unit == Unit::Page {
+        out.labels = pdf_page_labels(bytes)?;
+    }
+    Ok(out)
+}
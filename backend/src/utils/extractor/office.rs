@@ -0,0 +1,294 @@
+use anyhow::{Context, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashSet;
+use std::io::{Cursor, Read, Seek};
+use zip::ZipArchive;
+use crate::utils::canonical::Profile;
+use super::{Format, PageLeaf, Source, Unit};
+
+const ODT_MIMETYPE: &str = "application/vnd.oasis.opendocument.text";
+
+pub(super) fn detect<R: Read + Seek>(mut zip: ZipArchive<R>) -> Option<Format> {
+    if zip.by_name("word/document.xml").is_ok() {
+        return Some(Format::Docx);
+    }
+    let mut mimetype = String::new();
+    zip.by_name("mimetype").ok()?.read_to_string(&mut mimetype).ok()?;
+    (mimetype.trim() == ODT_MIMETYPE).then_some(Format::Odt)
+}
+
+/// DOCX body split at explicit page breaks (`<w:br w:type="page"/>`,
+/// `pageBreakBefore`) and section breaks; pages without text, such as the
+/// one before a break at the very start, are left out. A document without
+/// any breaks is split into paragraphs instead. Rendered page breaks are
+/// ignored: they depend on the viewer, not on the file.
+pub fn docx_to_pages(bytes: &[u8], profile: Profile) -> Result<(Vec<PageLeaf>, Unit)> {
+    let xml = read_part(bytes, "word/document.xml")?;
+    let mut reader = Reader::from_str(&xml);
+    let mut units = Units::new();
+    let mut in_text = false;
+    let mut pending_sect_break = false;
+
+    loop {
+        match reader.read_event().context("parse word/document.xml")? {
+            Event::Start(e) => match e.name().as_ref() {
+                b"w:p" => units.start_paragraph(),
+                b"w:t" => in_text = true,
+                _      => {}
+            },
+            Event::Empty(e) => match e.name().as_ref() {
+                b"w:p"   => { units.start_paragraph(); units.end_paragraph(); }
+                b"w:tab" => units.push_str("\t"),
+                b"w:cr"  => units.push_str("\n"),
+                b"w:br"  => match attr(&e, "w:type").as_deref() {
+                    Some("page")   => units.page_break(),
+                    Some("column") => {}
+                    _              => units.push_str("\n"),
+                },
+                b"w:sectPr" => pending_sect_break = units.in_paragraph(),
+                b"w:pageBreakBefore"
+                    if !matches!(attr(&e, "w:val").as_deref(), Some("0" | "false" | "off")) =>
+                {
+                    units.page_break()
+                }
+                _ => {}
+            },
+            // a sectPr inside a paragraph's pPr ends a section after that
+            // paragraph; the body-level one just describes the last section
+            Event::End(e) => match e.name().as_ref() {
+                b"w:t"      => in_text = false,
+                b"w:sectPr" => pending_sect_break = units.in_paragraph(),
+                b"w:p"      => {
+                    units.end_paragraph();
+                    if pending_sect_break {
+                        units.page_break();
+                        pending_sect_break = false;
+                    }
+                }
+                _ => {}
+            },
+            Event::Text(t) if in_text => units.push_str(&t.unescape()?),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(units.finish(profile))
+}
+
+/// ODT body split at paragraphs whose automatic style forces a page break
+/// (`fo:break-before` / `fo:break-after`), falling back to paragraphs;
+/// pages without text are left out, as for DOCX. Annotations and tracked
+/// deletions are not document text.
+pub fn odt_to_pages(bytes: &[u8], profile: Profile) -> Result<(Vec<PageLeaf>, Unit)> {
+    let xml = read_part(bytes, "content.xml")?;
+    let mut reader = Reader::from_str(&xml);
+    let mut units = Units::new();
+    let mut break_before: HashSet<String> = HashSet::new();
+    let mut break_after:  HashSet<String> = HashSet::new();
+    let mut current_style: Option<String> = None;
+    let mut para_styles: Vec<Option<String>> = Vec::new();
+    let mut skip_depth = 0usize;
+
+    loop {
+        match reader.read_event().context("parse content.xml")? {
+            Event::Start(e) => {
+                let name = e.name();
+                if skip_depth > 0 || matches!(name.as_ref(), b"office:annotation" | b"text:tracked-changes") {
+                    skip_depth += 1;
+                    continue;
+                }
+                match name.as_ref() {
+                    b"style:style" => current_style = attr(&e, "style:name"),
+                    b"style:paragraph-properties" => paragraph_breaks(&e, &current_style, &mut break_before, &mut break_after),
+                    b"text:p" | b"text:h" => {
+                        let style = attr(&e, "text:style-name");
+                        if style.as_ref().is_some_and(|s| break_before.contains(s)) {
+                            units.page_break();
+                        }
+                        units.start_paragraph();
+                        para_styles.push(style);
+                    }
+                    _ => {}
+                }
+            }
+            Event::Empty(e) if skip_depth == 0 => match e.name().as_ref() {
+                b"style:paragraph-properties" => paragraph_breaks(&e, &current_style, &mut break_before, &mut break_after),
+                b"text:p" | b"text:h" => {
+                    let style = attr(&e, "text:style-name");
+                    if style.as_ref().is_some_and(|s| break_before.contains(s)) {
+                        units.page_break();
+                    }
+                    units.start_paragraph();
+                    units.end_paragraph();
+                    if style.is_some_and(|s| break_after.contains(&s)) {
+                        units.page_break();
+                    }
+                }
+                b"text:s" => {
+                    let n = attr(&e, "text:c").and_then(|c| c.parse().ok()).unwrap_or(1usize);
+                    units.push_str(&" ".repeat(n));
+                }
+                b"text:tab"        => units.push_str("\t"),
+                b"text:line-break" => units.push_str("\n"),
+                _ => {}
+            },
+            Event::End(e) => {
+                if skip_depth > 0 {
+                    skip_depth -= 1;
+                    continue;
+                }
+                match e.name().as_ref() {
+                    b"style:style" => current_style = None,
+                    b"text:p" | b"text:h" => {
+                        units.end_paragraph();
+                        if para_styles.pop().flatten().is_some_and(|s| break_after.contains(&s)) {
+                            units.page_break();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(t) if skip_depth == 0 && units.in_paragraph() => units.push_str(&t.unescape()?),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(units.finish(profile))
+}
+
+fn paragraph_breaks(
+    e:      &BytesStart,
+    style:  &Option<String>,
+    before: &mut HashSet<String>,
+    after:  &mut HashSet<String>,
+) {
+    let Some(style) = style else { return };
+    if attr(e, "fo:break-before").as_deref() == Some("page") {
+        before.insert(style.clone());
+    }
+    if attr(e, "fo:break-after").as_deref() == Some("page") {
+        after.insert(style.clone());
+    }
+}
+
+fn read_part(bytes: &[u8], name: &str) -> Result<String> {
+    let mut zip = ZipArchive::new(Cursor::new(bytes)).context("open zip container")?;
+    let mut part = zip.by_name(name).with_context(|| format!("missing {name}"))?;
+    let mut xml = String::new();
+    part.read_to_string(&mut xml).with_context(|| format!("read {name}"))?;
+    Ok(xml)
+}
+
+fn attr(e: &BytesStart, name: &str) -> Option<String> {
+    let a = e.try_get_attribute(name).ok()??;
+    a.unescape_value().ok().map(|v| v.into_owned())
+}
+
+/// Paragraph text grouped into explicit pages. Paragraphs can nest (text
+/// boxes, footnotes); an inner paragraph is emitted before its outer one.
+struct Units {
+    pages: Vec<Vec<String>>,
+    open:  Vec<String>,
+}
+
+impl Units {
+    fn new() -> Self {
+        Units { pages: vec![Vec::new()], open: Vec::new() }
+    }
+
+    fn in_paragraph(&self) -> bool {
+        !self.open.is_empty()
+    }
+
+    fn start_paragraph(&mut self) {
+        self.open.push(String::new());
+    }
+
+    fn push_str(&mut self, s: &str) {
+        if let Some(p) = self.open.last_mut() {
+            p.push_str(s);
+        }
+    }
+
+    fn end_paragraph(&mut self) {
+        if let Some(p) = self.open.pop() {
+            self.pages.last_mut().unwrap().push(p);
+        }
+    }
+
+    // text of an open paragraph before the break stays on the old page
+    fn page_break(&mut self) {
+        if let Some(p) = self.open.last_mut() {
+            if !p.is_empty() {
+                let head = std::mem::take(p);
+                self.pages.last_mut().unwrap().push(head);
+            }
+        }
+        self.pages.push(Vec::new());
+    }
+
+    fn finish(mut self, profile: Profile) -> (Vec<PageLeaf>, Unit) {
+        while self.in_paragraph() {
+            self.end_paragraph();
+        }
+        let leaf = |text: &str| PageLeaf::new(profile.apply(text).into_bytes(), Source::Text);
+
+        // an empty page would hash `""` like every other one
+        if self.pages.len() > 1 {
+            let pages = self.pages
+                .iter()
+                .map(|paras| paras.join("\n"))
+                .filter(|text| !text.trim().is_empty())
+                .map(|text| leaf(&text))
+                .collect();
+            (pages, Unit::Page)
+        } else {
+            let paras = self.pages
+                .concat()
+                .iter()
+                .filter(|p| !p.trim().is_empty())
+                .map(|p| leaf(p))
+                .collect();
+            (paras, Unit::Paragraph)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn docx(body: &str) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("word/document.xml", zip::write::FileOptions::default()).unwrap();
+        write!(zip, r#"<w:document xmlns:w="w"><w:body>{body}</w:body></w:document>"#).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn texts(leaves: &[PageLeaf]) -> Vec<&str> {
+        leaves.iter().map(|l| std::str::from_utf8(&l.payload).unwrap()).collect()
+    }
+
+    #[test]
+    fn breaks_at_the_edges_add_no_pages() {
+        let body = r#"<w:p><w:r><w:br w:type="page"/></w:r></w:p>
+            <w:p><w:r><w:t>one</w:t></w:r></w:p>
+            <w:p><w:r><w:br w:type="page"/><w:t>two</w:t></w:r></w:p>
+            <w:p><w:r><w:br w:type="page"/></w:r></w:p>"#;
+        let (leaves, unit) = docx_to_pages(&docx(body), Profile::NfkcV1).unwrap();
+        assert_eq!(unit, Unit::Page);
+        assert_eq!(texts(&leaves), ["one", "two"]);
+    }
+
+    #[test]
+    fn without_breaks_paragraphs_are_units() {
+        let body = "<w:p><w:r><w:t>one</w:t></w:r></w:p><w:p/><w:p><w:r><w:t>two</w:t></w:r></w:p>";
+        let (leaves, unit) = docx_to_pages(&docx(body), Profile::Raw).unwrap();
+        assert_eq!(unit, Unit::Paragraph);
+        assert_eq!(texts(&leaves), ["one", "two"]);
+    }
+}
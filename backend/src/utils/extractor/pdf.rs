@@ -0,0 +1,489 @@
+use anyhow::{bail, Context, Result};
+use lopdf::encryption::DecryptionError;
+use lopdf::{content::Content, Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::{HashMap, HashSet};
+use crate::merkle::coreFunctions::generate_hash;
+use crate::utils::canonical::Profile;
+use super::{PageLeaf, Source, Status};
+
+/// Decrypts a password-protected PDF in memory and returns it re-saved
+/// without `/Encrypt`, or `None` when the file isn't encrypted. With no
+/// password the empty user password is tried, which opens files that only
+/// restrict printing or copying. lopdf handles the RC4 standard security
+/// handler (V 1–2); AES-encrypted files are rejected.
+///
+/// Leaves are derived from the re-saved copy, so embedded signatures of
+/// an encrypted file report `byte_range_ok: false`.
+pub fn decrypt_pdf(bytes: &[u8], password: Option<&str>) -> Result<Option<Vec<u8>>> {
+    let mut doc = Document::load_mem(bytes).context("parse PDF")?;
+    if !doc.is_encrypted() {
+        return Ok(None);
+    }
+    match doc.decrypt(password.unwrap_or_default()) {
+        Ok(()) => {}
+        Err(lopdf::Error::Decryption(DecryptionError::IncorrectPassword)) => match password {
+            Some(_) => bail!("wrong password for encrypted PDF"),
+            None    => bail!("PDF is encrypted; send its password in the `password` field"),
+        },
+        Err(lopdf::Error::Decryption(DecryptionError::UnsupportedEncryption)) => {
+            bail!("unsupported PDF encryption (only RC4, V 1–2, can be decrypted)")
+        }
+        Err(e) => bail!("decrypt PDF: {e}"),
+    }
+    let mut out = Vec::with_capacity(bytes.len());
+    doc.save_to(&mut out).context("re-save decrypted PDF")?;
+    Ok(Some(out))
+}
+
+/// Whether the file is encrypted and `password` (or the empty user
+/// password) doesn't open it; other failures aren't fixed by a password.
+pub fn needs_password(bytes: &[u8], password: Option<&str>) -> bool {
+    let Ok(mut doc) = Document::load_mem(bytes) else { return false };
+    doc.is_encrypted()
+        && matches!(
+            doc.decrypt(password.unwrap_or_default()),
+            Err(lopdf::Error::Decryption(DecryptionError::IncorrectPassword))
+        )
+}
+
+/// Canonicalized text per page. A page that doesn't yield text never fails
+/// the upload: scans and photos are hashed from their embedded images,
+/// blank pages and pages whose text can't be extracted from their content
+/// stream (as in content mode). Each leaf records which of these happened.
+pub fn pdf_to_pages(bytes: &[u8], profile: Profile) -> Result<Vec<PageLeaf>> {
+    let doc = Document::load_mem(bytes).context("parse PDF")?;
+    text_pages(&doc, profile, |page_no| doc.extract_text(&[page_no]).map_err(|e| e.to_string()))
+}
+
+/// `pdf_to_pages` with the text layer read by pdf-extract, which handles
+/// more font encodings than lopdf. It reads the whole file at once, so if
+/// it fails every page falls back to its content stream.
+pub fn pdf_extract_pages(bytes: &[u8], profile: Profile) -> Result<Vec<PageLeaf>> {
+    let doc = Document::load_mem(bytes).context("parse PDF")?;
+    // pdf-extract panics on some malformed files instead of erroring
+    let texts = match std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(bytes)) {
+        Ok(Ok(texts)) => Ok(texts),
+        Ok(Err(e))    => Err(format!("pdf-extract: {e}")),
+        Err(_)        => Err("pdf-extract panicked".to_owned()),
+    };
+    text_pages(&doc, profile, |page_no| match &texts {
+        Ok(texts) => texts
+            .get(page_no as usize - 1)
+            .cloned()
+            .ok_or_else(|| format!("pdf-extract returned no text for page {page_no}")),
+        Err(e) => Err(e.clone()),
+    })
+}
+
+fn text_pages(
+    doc:     &Document,
+    profile: Profile,
+    text_of: impl Fn(u32) -> std::result::Result<String, String>,
+) -> Result<Vec<PageLeaf>> {
+    let pages = doc.get_pages();
+    let mut out = Vec::with_capacity(pages.len());
+
+    for (page_no, page_id) in pages {
+        let leaf = match text_of(page_no) {
+            Ok(raw) => {
+                let text = profile.apply(&raw);
+                if !text.trim().is_empty() {
+                    PageLeaf::new(text.into_bytes(), Source::Text)
+                } else if let Some(payload) = page_images_payload(doc, page_id) {
+                    PageLeaf::new(payload, Source::Images).with_status(Status::FallbackUsed, None)
+                } else {
+                    content_fallback(doc, page_id, Status::Empty, None)
+                }
+            }
+            Err(e) => content_fallback(doc, page_id, Status::Failed, Some(e)),
+        };
+        out.push(leaf);
+    }
+
+    Ok(out)
+}
+
+// A page without usable text is committed by its content stream; if even
+// that can't be read, the leaf is empty and the page marked failed.
+fn content_fallback(doc: &Document, page_id: ObjectId, status: Status, error: Option<String>) -> PageLeaf {
+    match page_content_payload(doc, page_id) {
+        Ok(payload) => PageLeaf::new(payload, Source::Content).with_status(status, error),
+        Err(e) => {
+            let error = match error {
+                Some(text_err) => format!("{text_err}; content: {e}"),
+                None           => format!("content: {e}"),
+            };
+            PageLeaf::new(Vec::new(), Source::Text).with_status(Status::Failed, Some(error))
+        }
+    }
+}
+
+/// One canonical byte string per page covering everything that is drawn:
+/// the decoded content streams plus the XObjects and fonts they reference.
+/// Object numbers, stream filters and dictionary order do not leak into the
+/// output, so re-saving the PDF keeps the same leaves.
+pub fn pdf_to_content_pages(bytes: &[u8]) -> Result<Vec<PageLeaf>> {
+    let doc = Document::load_mem(bytes).context("parse PDF")?;
+
+    let pages = doc.get_pages();
+    let mut out = Vec::with_capacity(pages.len());
+
+    for (page_no, page_id) in pages {
+        let payload = page_content_payload(&doc, page_id)
+            .with_context(|| format!("page {page_no} content"))?;
+        out.push(PageLeaf::new(payload, Source::Content));
+    }
+
+    Ok(out)
+}
+
+// Images in drawing order, each as size + hash of its pixel data. `None`
+// when the page draws no image at all.
+fn page_images_payload(doc: &Document, page_id: ObjectId) -> Option<Vec<u8>> {
+    let content = doc.get_page_content(page_id).ok()?;
+    let mut images = Vec::new();
+    let mut walk = FormWalk { visited: HashSet::new(), budget: MAX_DO_VISITS };
+    collect_images(doc, &content, &page_resource_dicts(doc, page_id), 0, &mut walk, &mut images);
+    if images.is_empty() {
+        return None;
+    }
+
+    let mut out = b"images-v1\n".to_vec();
+    for stream in images {
+        for key in [&b"Width"[..], b"Height", b"BitsPerComponent"] {
+            let v = stream.dict.get(key).and_then(Object::as_i64).unwrap_or(0);
+            out.push(b'I');
+            out.extend_from_slice(v.to_string().as_bytes());
+            out.push(b';');
+        }
+        write_bytes(&mut out, b'H', generate_hash(image_data(stream)).as_bytes());
+    }
+    Some(out)
+}
+
+/// `Do` operators followed per page, images and forms alike
+const MAX_DO_VISITS: usize = 4096;
+
+// Forms already walked on this page, and how many more `Do`s may be
+// followed: a form drawing itself, or drawn k times on each of several
+// levels, would otherwise be decoded k^depth times.
+struct FormWalk {
+    visited: HashSet<ObjectId>,
+    budget:  usize,
+}
+
+// follows `Do` operators through nested form XObjects, each form once
+fn collect_images<'a>(
+    doc:       &'a Document,
+    content:   &[u8],
+    resources: &[&'a Dictionary],
+    depth:     usize,
+    walk:      &mut FormWalk,
+    out:       &mut Vec<&'a Stream>,
+) {
+    const MAX_FORM_DEPTH: usize = 8;
+    let ops = match Content::decode(content) {
+        Ok(c)  => c.operations,
+        Err(_) => return,
+    };
+
+    for op in ops.iter().filter(|op| op.operator == "Do") {
+        if walk.budget == 0 {
+            return;
+        }
+        walk.budget -= 1;
+        let Some(name) = op.operands.first().and_then(|o| o.as_name().ok()) else {
+            continue;
+        };
+        let Some((id, stream)) = lookup_xobject(doc, resources, name) else {
+            continue;
+        };
+        match stream.dict.get(b"Subtype").and_then(Object::as_name) {
+            Ok(b"Image") => out.push(stream),
+            Ok(b"Form") if depth < MAX_FORM_DEPTH && id.is_none_or(|id| walk.visited.insert(id)) => {
+                let mut inner: Vec<&Dictionary> = stream
+                    .dict
+                    .get(b"Resources")
+                    .and_then(|o| doc.dereference(o))
+                    .and_then(|(_, o)| o.as_dict())
+                    .into_iter()
+                    .collect();
+                inner.extend_from_slice(resources);
+                let data = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+                collect_images(doc, &data, &inner, depth + 1, walk, out);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn lookup_xobject<'a>(
+    doc:       &'a Document,
+    resources: &[&'a Dictionary],
+    name:      &[u8],
+) -> Option<(Option<ObjectId>, &'a Stream)> {
+    resources.iter().find_map(|res| {
+        let (_, xobjects) = res.get(b"XObject").and_then(|o| doc.dereference(o)).ok()?;
+        let (id, obj) = xobjects.as_dict().ok()?.get(name).and_then(|o| doc.dereference(o)).ok()?;
+        Some((id, obj.as_stream().ok()?))
+    })
+}
+
+// Decoded samples for Flate/LZW images; lopdf refuses to decode anything
+// tagged `/Subtype /Image`, so decode a copy without the tag. JPEG, JPX,
+// CCITT and JBIG2 are left as their raw stream bytes.
+fn image_data(stream: &Stream) -> Vec<u8> {
+    let mut plain = stream.clone();
+    plain.dict.remove(b"Subtype");
+    plain.decompressed_content().unwrap_or_else(|_| stream.content.clone())
+}
+
+fn page_content_payload(doc: &Document, page_id: ObjectId) -> Result<Vec<u8>> {
+    let mut out = b"content-v1\n".to_vec();
+
+    let content = doc.get_page_content(page_id)?;
+    write_bytes(&mut out, b'B', &content);
+
+    // page resources win over inherited ones, like in a viewer
+    let mut xobjects: Vec<(Vec<u8>, &Object)> = Vec::new();
+    let mut fonts:    Vec<(Vec<u8>, &Object)> = Vec::new();
+    for resources in page_resource_dicts(doc, page_id) {
+        collect_named(doc, resources, b"XObject", &mut xobjects);
+        collect_named(doc, resources, b"Font", &mut fonts);
+    }
+    xobjects.sort_by(|a, b| a.0.cmp(&b.0));
+    fonts.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut ser = Canonicalizer::new(doc);
+    for (tag, entries) in [(b'X', &xobjects), (b'F', &fonts)] {
+        out.push(tag);
+        out.extend_from_slice(entries.len().to_string().as_bytes());
+        out.push(b'{');
+        for (name, obj) in entries {
+            write_bytes(&mut out, b'N', name);
+            ser.write(obj, &mut out)?;
+        }
+        out.push(b'}');
+    }
+
+    Ok(out)
+}
+
+fn page_resource_dicts(doc: &Document, page_id: ObjectId) -> Vec<&Dictionary> {
+    let (own, inherited) = doc.get_page_resources(page_id);
+    own.into_iter()
+        .chain(inherited.into_iter().filter_map(|id| doc.get_dictionary(id).ok()))
+        .collect()
+}
+
+fn collect_named<'a>(
+    doc:       &'a Document,
+    resources: &'a Dictionary,
+    category:  &[u8],
+    out:       &mut Vec<(Vec<u8>, &'a Object)>,
+) {
+    let dict = match resources.get(category).and_then(|o| doc.dereference(o)) {
+        Ok((_, Object::Dictionary(d))) => d,
+        _ => return,
+    };
+    for (name, obj) in dict.iter() {
+        if !out.iter().any(|(n, _)| n == name) {
+            out.push((name.clone(), obj));
+        }
+    }
+}
+
+fn write_bytes(out: &mut Vec<u8>, tag: u8, bytes: &[u8]) {
+    out.push(tag);
+    out.extend_from_slice(bytes.len().to_string().as_bytes());
+    out.push(b':');
+    out.extend_from_slice(bytes);
+}
+
+/// Tag/length-prefixed serialization of a PDF object graph. References are
+/// followed; an object seen before is written as a back-reference to the
+/// order in which it was first serialized, which keeps shared fonts cheap
+/// and makes cycles (`/Parent`, `/P`) terminate. A chain of objects nested
+/// deeper than `MAX_NESTING` is an error rather than a stack overflow.
+struct Canonicalizer<'a> {
+    doc:   &'a Document,
+    seen:  HashMap<ObjectId, usize>,
+    depth: usize,
+}
+
+const MAX_NESTING: usize = 256;
+
+impl<'a> Canonicalizer<'a> {
+    fn new(doc: &'a Document) -> Self {
+        Canonicalizer { doc, seen: HashMap::new(), depth: 0 }
+    }
+
+    fn write(&mut self, obj: &Object, out: &mut Vec<u8>) -> Result<()> {
+        if self.depth == MAX_NESTING {
+            bail!("objects nested more than {MAX_NESTING} levels deep");
+        }
+        self.depth += 1;
+        let written = self.write_object(obj, out);
+        self.depth -= 1;
+        written
+    }
+
+    fn write_object(&mut self, obj: &Object, out: &mut Vec<u8>) -> Result<()> {
+        match obj {
+            Object::Null          => out.push(b'Z'),
+            Object::Boolean(b)    => out.push(if *b { b'T' } else { b'F' }),
+            Object::Integer(i)    => {
+                out.push(b'I');
+                out.extend_from_slice(i.to_string().as_bytes());
+                out.push(b';');
+            }
+            Object::Real(r)       => {
+                out.push(b'R');
+                out.extend_from_slice(format!("{r:?}").as_bytes());
+                out.push(b';');
+            }
+            Object::Name(n)       => write_bytes(out, b'N', n),
+            Object::String(s, _)  => write_bytes(out, b'S', s),
+            Object::Array(items)  => {
+                out.push(b'A');
+                out.extend_from_slice(items.len().to_string().as_bytes());
+                out.push(b'[');
+                for item in items {
+                    self.write(item, out)?;
+                }
+                out.push(b']');
+            }
+            Object::Dictionary(d) => self.write_dict(d, &[], out)?,
+            Object::Stream(s)     => {
+                // the encoding of a stream is not content: drop it
+                self.write_dict(&s.dict, &[b"Length", b"Filter", b"DecodeParms"], out)?;
+                let data = s.decompressed_content().unwrap_or_else(|_| s.content.clone());
+                write_bytes(out, b'B', &data);
+            }
+            Object::Reference(id) => {
+                if let Some(n) = self.seen.get(id) {
+                    out.push(b'@');
+                    out.extend_from_slice(n.to_string().as_bytes());
+                    out.push(b';');
+                    return Ok(());
+                }
+                let n = self.seen.len();
+                self.seen.insert(*id, n);
+                match self.doc.get_object(*id) {
+                    Ok(target) => self.write(target, out)?,
+                    Err(_)     => out.push(b'Z'),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_dict(&mut self, dict: &Dictionary, skip: &[&[u8]], out: &mut Vec<u8>) -> Result<()> {
+        // `/Parent` points back up into the page tree, i.e. at other pages
+        let mut entries: Vec<_> = dict
+            .iter()
+            .filter(|(k, _)| k.as_slice() != b"Parent" && !skip.contains(&k.as_slice()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+
+        out.push(b'D');
+        out.extend_from_slice(entries.len().to_string().as_bytes());
+        out.push(b'{');
+        for (key, value) in entries {
+            write_bytes(out, b'N', key);
+            self.write(value, out)?;
+        }
+        out.push(b'}');
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forms_are_walked_once() {
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let form_id = doc.new_object_id();
+        let image = doc.add_object(Stream::new(
+            Dictionary::from_iter([
+                ("Type", Object::Name(b"XObject".to_vec())),
+                ("Subtype", Object::Name(b"Image".to_vec())),
+                ("Width", Object::Integer(1)),
+                ("Height", Object::Integer(1)),
+                ("BitsPerComponent", Object::Integer(8)),
+                ("ColorSpace", Object::Name(b"DeviceGray".to_vec())),
+            ]),
+            vec![0x80],
+        ));
+        // draws the image, then itself ten times
+        let xobjects = |image| Dictionary::from_iter([(
+            "XObject",
+            Object::Dictionary(Dictionary::from_iter([
+                ("Fm0", Object::Reference(form_id)),
+                ("Im0", Object::Reference(image)),
+            ])),
+        )]);
+        doc.objects.insert(form_id, Object::Stream(Stream::new(
+            Dictionary::from_iter([
+                ("Type", Object::Name(b"XObject".to_vec())),
+                ("Subtype", Object::Name(b"Form".to_vec())),
+                ("BBox", Object::Array(vec![0.into(), 0.into(), 10.into(), 10.into()])),
+                ("Resources", Object::Dictionary(xobjects(image))),
+            ]),
+            format!("/Im0 Do{}", " /Fm0 Do".repeat(10)).into_bytes(),
+        )));
+        let content = doc.add_object(Stream::new(Dictionary::new(), b"/Fm0 Do".to_vec()));
+        let page = doc.add_object(Dictionary::from_iter([
+            ("Type", Object::Name(b"Page".to_vec())),
+            ("Parent", Object::Reference(pages_id)),
+            ("Contents", Object::Reference(content)),
+            ("Resources", Object::Dictionary(xobjects(image))),
+        ]));
+        doc.objects.insert(pages_id, Object::Dictionary(Dictionary::from_iter([
+            ("Type", Object::Name(b"Pages".to_vec())),
+            ("Count", Object::Integer(1)),
+            ("MediaBox", Object::Array(vec![0.into(), 0.into(), 612.into(), 792.into()])),
+            ("Kids", Object::Array(vec![Object::Reference(page)])),
+        ])));
+
+        let payload = page_images_payload(&doc, page).unwrap();
+        let images = payload.windows(2).filter(|w| w == b"H6").count();
+        assert_eq!(images, 1);
+    }
+
+    #[test]
+    fn long_reference_chains_are_an_error() {
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        // obj 1 → [2 0 R] → [3 0 R] → …, far deeper than the stack allows
+        let first = doc.new_object_id();
+        let mut id = first;
+        for _ in 0..100_000 {
+            let next = doc.new_object_id();
+            doc.objects.insert(id, Object::Array(vec![Object::Reference(next)]));
+            id = next;
+        }
+        doc.objects.insert(id, Object::Null);
+        let content = doc.add_object(Stream::new(Dictionary::new(), b"0 0 10 10 re f".to_vec()));
+        let page = doc.add_object(Dictionary::from_iter([
+            ("Type", Object::Name(b"Page".to_vec())),
+            ("Parent", Object::Reference(pages_id)),
+            ("Contents", Object::Reference(content)),
+            ("Resources", Object::Dictionary(Dictionary::from_iter([(
+                "Font",
+                Object::Dictionary(Dictionary::from_iter([("F0", Object::Reference(first))])),
+            )]))),
+        ]));
+        doc.objects.insert(pages_id, Object::Dictionary(Dictionary::from_iter([
+            ("Type", Object::Name(b"Pages".to_vec())),
+            ("Count", Object::Integer(1)),
+            ("Kids", Object::Array(vec![Object::Reference(page)])),
+        ])));
+
+        let err = page_content_payload(&doc, page).unwrap_err();
+        assert!(err.to_string().contains("nested"), "{err}");
+    }
+}
@@ -0,0 +1,36 @@
+use super::{PageLeaf, Source};
+
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+pub const MAX_CHUNK_SIZE:     usize = 64 * 1024 * 1024;
+
+/// Splits the file into `chunk_size` byte leaves; the last one may be
+/// shorter. Chunk `i` covers bytes `i * chunk_size ..`. An empty file is a
+/// single empty chunk. Chunks are hashed in place, not copied: the upload
+/// may be a file mapped from the disk spool.
+pub fn chunk_leaves(bytes: &[u8], chunk_size: usize) -> Vec<PageLeaf> {
+    if bytes.is_empty() {
+        return vec![PageLeaf::hashed(&[], Source::Chunk)];
+    }
+    bytes
+        .chunks(chunk_size)
+        .map(|c| PageLeaf::hashed(c, Source::Chunk))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::coreFunctions::generate_hash;
+
+    #[test]
+    fn chunks_keep_only_their_hash() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let leaves = chunk_leaves(&bytes, 100);
+        assert_eq!(leaves.len(), 3);
+        for (leaf, chunk) in leaves.iter().zip(bytes.chunks(100)) {
+            assert!(leaf.payload.is_empty());
+            assert_eq!(leaf.hash.as_deref(), Some(generate_hash(chunk).as_str()));
+        }
+        assert_eq!(chunk_leaves(&[], 100)[0].hash.as_deref(), Some(generate_hash(b"").as_str()));
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::{bail, Result};
+use super::{Format, Mode, Options, PageLeaf, Unit};
+
+/// An engine that turns one file format into leaves. Name and version are
+/// stored on every document, so its proofs can be regenerated with the
+/// engine that produced them.
+pub trait PageExtractor: Sync {
+    fn name(&self) -> &'static str;
+    /// Changes whenever the same file could come out as different leaves.
+    fn version(&self) -> &'static str;
+    fn handles(&self, format: Format, mode: Mode) -> bool;
+    fn pages(&self, bytes: &[u8], opts: &Options) -> Result<(Vec<PageLeaf>, Unit)>;
+}
+
+/// Every engine. For a format and mode the first one that handles it is
+/// the default; an upload may name another with `extractor=`.
+pub static EXTRACTORS: [&dyn PageExtractor; 7] = [&Chunks, &Lopdf, &PdfExtract, &Docx, &Odt, &Json, &Csv];
+
+pub fn by_name(name: &str) -> Option<&'static dyn PageExtractor> {
+    EXTRACTORS.iter().copied().find(|e| e.name() == name)
+}
+
+/// The engine named in `opts`, which must handle this format and mode, or
+/// else the default.
+pub fn select(format: Format, opts: &Options) -> Result<&'static dyn PageExtractor> {
+    let handles = |e: &&dyn PageExtractor| e.handles(format, opts.mode);
+    if let Some(name) = opts.extractor {
+        return match by_name(name).filter(handles) {
+            Some(engine) => Ok(engine),
+            None => bail!("extractor {name} doesn't handle {} in {} mode", format.id(), opts.mode.id()),
+        };
+    }
+    match EXTRACTORS.iter().copied().find(handles) {
+        Some(engine) => Ok(engine),
+        None => bail!("no extractor for {} in {} mode", format.id(), opts.mode.id()),
+    }
+}
+
+/// `opts` for a file inside an archive or e-mail: an engine named for the
+/// upload that doesn't handle this one — `pdf-extract` for a DOCX member —
+/// gives way to the default.
+pub fn for_member(format: Format, opts: &Options) -> Options {
+    let extractor = opts.extractor.filter(|name| by_name(name).is_some_and(|e| e.handles(format, opts.mode)));
+    Options { extractor, ..*opts }
+}
+
+/// lopdf, which form mode builds on as well.
+pub const LOPDF: &dyn PageExtractor = &Lopdf;
+
+struct Chunks;
+struct Lopdf;
+struct PdfExtract;
+struct Docx;
+struct Odt;
+struct Json;
+struct Csv;
+
+impl PageExtractor for Chunks {
+    fn name(&self) -> &'static str { "chunks" }
+    fn version(&self) -> &'static str { "1" }
+    fn handles(&self, _: Format, mode: Mode) -> bool { mode == Mode::Raw }
+    fn pages(&self, bytes: &[u8], opts: &Options) -> Result<(Vec<PageLeaf>, Unit)> {
+        Ok((super::chunk_leaves(bytes, opts.chunk_size), Unit::Chunk))
+    }
+}
+
+impl PageExtractor for Lopdf {
+    fn name(&self) -> &'static str { "lopdf" }
+    fn version(&self) -> &'static str { env!("LOPDF_VERSION") }
+    fn handles(&self, format: Format, mode: Mode) -> bool {
+        format == Format::Pdf && matches!(mode, Mode::Text | Mode::Content | Mode::PagePdf)
+    }
+    fn pages(&self, bytes: &[u8], opts: &Options) -> Result<(Vec<PageLeaf>, Unit)> {
+        let pages = match opts.mode {
+            Mode::Content => super::pdf_to_content_pages(bytes)?,
+            Mode::PagePdf => super::pdf_page_files(bytes)?,
+            _             => super::pdf_to_pages(bytes, opts.profile)?,
+        };
+        Ok((pages, Unit::Page))
+    }
+}
+
+impl PageExtractor for PdfExtract {
+    fn name(&self) -> &'static str { "pdf-extract" }
+    fn version(&self) -> &'static str { env!("PDF_EXTRACT_VERSION") }
+    fn handles(&self, format: Format, mode: Mode) -> bool {
+        format == Format::Pdf && mode == Mode::Text
+    }
+    fn pages(&self, bytes: &[u8], opts: &Options) -> Result<(Vec<PageLeaf>, Unit)> {
+        Ok((super::pdf_extract_pages(bytes, opts.profile)?, Unit::Page))
+    }
+}
+
+impl PageExtractor for Docx {
+    fn name(&self) -> &'static str { "docx" }
+    fn version(&self) -> &'static str { "1" }
+    fn handles(&self, format: Format, mode: Mode) -> bool {
+        format == Format::Docx && mode == Mode::Text
+    }
+    fn pages(&self, bytes: &[u8], opts: &Options) -> Result<(Vec<PageLeaf>, Unit)> {
+        super::docx_to_pages(bytes, opts.profile)
+    }
+}
+
+impl PageExtractor for Odt {
+    fn name(&self) -> &'static str { "odt" }
+    fn version(&self) -> &'static str { "1" }
+    fn handles(&self, format: Format, mode: Mode) -> bool {
+        format == Format::Odt && mode == Mode::Text
+    }
+    fn pages(&self, bytes: &[u8], opts: &Options) -> Result<(Vec<PageLeaf>, Unit)> {
+        super::odt_to_pages(bytes, opts.profile)
+    }
+}
+
+impl PageExtractor for Json {
+    fn name(&self) -> &'static str { "json" }
+    fn version(&self) -> &'static str { "1" }
+    fn handles(&self, format: Format, mode: Mode) -> bool {
+        format == Format::Json && mode == Mode::Text
+    }
+    fn pages(&self, bytes: &[u8], _: &Options) -> Result<(Vec<PageLeaf>, Unit)> {
+        Ok((super::json_to_leaves(bytes)?, Unit::Field))
+    }
+}
+
+impl PageExtractor for Csv {
+    fn name(&self) -> &'static str { "csv" }
+    fn version(&self) -> &'static str { "1" }
+    fn handles(&self, format: Format, mode: Mode) -> bool {
+        format == Format::Csv && mode == Mode::Text
+    }
+    fn pages(&self, bytes: &[u8], _: &Options) -> Result<(Vec<PageLeaf>, Unit)> {
+        Ok((super::csv_to_leaves(bytes)?, Unit::Row))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::canonical::Profile;
+
+    fn opts(mode: Mode, extractor: Option<&'static str>) -> Options {
+        Options { mode, profile: Profile::DEFAULT, chunk_size: 4096, metadata: false, extractor }
+    }
+
+    #[test]
+    fn default_engines() {
+        assert_eq!(select(Format::Pdf, &opts(Mode::Text, None)).unwrap().name(), "lopdf");
+        assert_eq!(select(Format::Docx, &opts(Mode::Text, None)).unwrap().name(), "docx");
+        assert_eq!(select(Format::Pdf, &opts(Mode::Raw, None)).unwrap().name(), "chunks");
+    }
+
+    #[test]
+    fn named_engine_must_fit() {
+        assert_eq!(select(Format::Pdf, &opts(Mode::Text, Some("pdf-extract"))).unwrap().name(), "pdf-extract");
+        assert!(select(Format::Docx, &opts(Mode::Text, Some("pdf-extract"))).is_err());
+        assert!(select(Format::Pdf, &opts(Mode::Content, Some("pdf-extract"))).is_err());
+    }
+
+    #[test]
+    fn members_fall_back_to_the_default() {
+        let named = opts(Mode::Text, Some("pdf-extract"));
+        assert_eq!(for_member(Format::Pdf, &named).extractor, Some("pdf-extract"));
+        assert_eq!(for_member(Format::Docx, &named).extractor, None);
+    }
+
+    #[test]
+    fn versions_are_the_locked_ones() {
+        let lock = include_str!("../../../Cargo.lock");
+        for engine in [LOPDF, by_name("pdf-extract").unwrap()] {
+            let version = engine.version();
+            assert_eq!(version.split('.').filter(|p| p.parse::<u32>().is_ok()).count(), 3, "{version}");
+            let entry = format!("name = \"{}\"\nversion = \"{version}\"", engine.name());
+            assert!(lock.contains(&entry), "{entry}");
+        }
+    }
+}
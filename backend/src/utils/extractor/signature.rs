@@ -0,0 +1,294 @@
+use anyhow::{bail, Context, Result};
+use cms::cert::x509::der::{asn1::OctetString, oid::ObjectIdentifier, Decode, Encode, SliceReader};
+use cms::cert::x509::ext::pkix::SubjectKeyIdentifier;
+use cms::cert::x509::Certificate;
+use cms::cert::CertificateChoices;
+use cms::content_info::ContentInfo;
+use cms::signed_data::{SignedData, SignerIdentifier, SignerInfo};
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use serde::Serialize;
+use serde_json::json;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::collections::HashMap;
+use super::metadata::{decode_text_string, pdf_date_to_iso};
+use super::{PageLeaf, Source};
+
+const ID_MESSAGE_DIGEST: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.4");
+const ID_SUBJECT_KEY_ID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.14");
+
+/// One embedded signature as found in the file. `digest_ok` means the bytes
+/// covered by `/ByteRange` hash to the digest the signer signed; the
+/// signature value itself and the certificate chain are not validated.
+#[derive(Debug, Clone, Serialize)]
+pub struct Signature {
+    /// `/T` of the signature field, when the dictionary hangs off one
+    pub field:             Option<String>,
+    pub sub_filter:        Option<String>,
+    pub byte_range:        Vec<i64>,
+    /// starts at offset 0 and leaves out exactly the `/Contents` string
+    pub byte_range_ok:     bool,
+    /// false when revisions were appended after this signature; only a
+    /// stamp `utils::stamp` can reproduce doesn't count
+    pub covers_whole_file: bool,
+    pub digest_algorithm:  Option<String>,
+    /// `None` when the CMS blob can't be parsed or uses another digest
+    pub digest_ok:         Option<bool>,
+    /// subject DN of the signer's certificate
+    pub signer:            Option<String>,
+    /// `/M`, as claimed by the signing software
+    pub signing_time:      Option<String>,
+}
+
+/// Every `/ByteRange` signature dictionary of the PDF, in signing order.
+/// `end` is the length of the file as uploaded, see `stamp::original_len`.
+pub fn pdf_signatures(bytes: &[u8], end: usize) -> Result<Vec<Signature>> {
+    let doc = Document::load_mem(bytes).context("parse PDF")?;
+
+    // signature dictionaries are usually referenced from a field's /V
+    let mut field_names: HashMap<ObjectId, String> = HashMap::new();
+    let mut inline: Vec<(Option<String>, &Dictionary)> = Vec::new();
+    for obj in doc.objects.values() {
+        let Ok(dict) = obj.as_dict() else { continue };
+        if !matches!(dict.get(b"FT"), Ok(Object::Name(n)) if n == b"Sig") {
+            continue;
+        }
+        let name = match dict.get(b"T") {
+            Ok(Object::String(t, _)) => Some(decode_text_string(t)),
+            _ => None,
+        };
+        match dict.get(b"V") {
+            Ok(Object::Reference(id)) => {
+                if let Some(name) = name {
+                    field_names.insert(*id, name);
+                }
+            }
+            Ok(Object::Dictionary(v)) => inline.push((name, v)),
+            _ => {}
+        }
+    }
+
+    let mut out = Vec::new();
+    for (id, obj) in &doc.objects {
+        if let Ok(dict) = obj.as_dict() {
+            if dict.has(b"ByteRange") && dict.has(b"Contents") {
+                out.push(inspect(bytes, end, dict, field_names.get(id).cloned()));
+            }
+        }
+    }
+    for (name, dict) in inline {
+        if dict.has(b"ByteRange") && dict.has(b"Contents") {
+            out.push(inspect(bytes, end, dict, name));
+        }
+    }
+    out.sort_by_key(|s| (s.byte_range.get(3).copied(), s.byte_range.get(2).copied()));
+    Ok(out)
+}
+
+/// Signature report leaf, keyed `signatures`:
+/// `["pdf-signatures-v1", [signature, …]]`.
+pub fn signatures_leaf(signatures: &[Signature]) -> PageLeaf {
+    let payload = json!(["pdf-signatures-v1", signatures]).to_string().into_bytes();
+    PageLeaf::keyed("signatures".to_owned(), payload, Source::Signatures)
+}
+
+fn inspect(bytes: &[u8], end: usize, dict: &Dictionary, field: Option<String>) -> Signature {
+    let byte_range: Vec<i64> = match dict.get(b"ByteRange") {
+        Ok(Object::Array(items)) => items.iter().filter_map(|o| o.as_i64().ok()).collect(),
+        _ => Vec::new(),
+    };
+    let contents = match dict.get(b"Contents") {
+        Ok(Object::String(s, _)) => s.as_slice(),
+        _ => &[],
+    };
+    let name = |key: &[u8]| match dict.get(key) {
+        Ok(Object::Name(n)) => Some(String::from_utf8_lossy(n).into_owned()),
+        _ => None,
+    };
+    let signing_time = match dict.get(b"M") {
+        Ok(Object::String(m, _)) => {
+            let text = decode_text_string(m);
+            Some(pdf_date_to_iso(&text).unwrap_or(text))
+        }
+        _ => None,
+    };
+
+    let mut sig = Signature {
+        field,
+        sub_filter: name(b"SubFilter"),
+        byte_range: byte_range.clone(),
+        byte_range_ok: false,
+        covers_whole_file: false,
+        digest_algorithm: None,
+        digest_ok: None,
+        signer: None,
+        signing_time,
+    };
+
+    let Some((signed, signed_end)) = signed_bytes(bytes, &byte_range, contents) else {
+        return sig;
+    };
+    sig.byte_range_ok = true;
+    sig.covers_whole_file = signed_end == end;
+
+    if let Ok((algorithm, digest_ok, signer)) = check_cms(contents, &signed, sig.sub_filter.as_deref()) {
+        sig.digest_algorithm = Some(algorithm.to_owned());
+        sig.digest_ok = digest_ok;
+        sig.signer = signer;
+    }
+    sig
+}
+
+// [a, b, c, d] must cover 0‥b and c‥c+d with the hex /Contents string,
+// and nothing else, in the gap between them. Returns the signed bytes and
+// where they end, c+d.
+fn signed_bytes(bytes: &[u8], range: &[i64], contents: &[u8]) -> Option<(Vec<u8>, usize)> {
+    let [a, b, c, d] = <[i64; 4]>::try_from(range).ok()?;
+    let end = usize::try_from(c.checked_add(d)?).ok()?;
+    let (b, c, d) = (usize::try_from(b).ok()?, usize::try_from(c).ok()?, usize::try_from(d).ok()?);
+    if a != 0 || c < b || end > bytes.len() {
+        return None;
+    }
+    let gap = &bytes[b..c];
+    let hex = gap.strip_prefix(b"<")?.strip_suffix(b">")?;
+    if hex.len() != contents.len() * 2 || hex_decode(hex)? != contents {
+        return None;
+    }
+    let mut out = Vec::with_capacity(b + d);
+    out.extend_from_slice(&bytes[..b]);
+    out.extend_from_slice(&bytes[c..end]);
+    Some((out, end))
+}
+
+fn hex_decode(hex: &[u8]) -> Option<Vec<u8>> {
+    hex.chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+// (digest algorithm, whether the signed digest matches, signer subject)
+fn check_cms(contents: &[u8], signed: &[u8], sub_filter: Option<&str>) -> Result<(&'static str, Option<bool>, Option<String>)> {
+    // /Contents is zero-padded to its reserved size
+    let mut reader = SliceReader::new(contents)?;
+    let info = ContentInfo::decode(&mut reader)?;
+    let data: SignedData = info.content.decode_as()?;
+    let Some(signer_info) = data.signer_infos.0.iter().next() else {
+        bail!("no signer");
+    };
+
+    let oid = signer_info.digest_alg.oid.to_string();
+    let (algorithm, digest) = match digest(&oid, signed) {
+        Some(d) => d,
+        None    => bail!("unsupported digest {oid}"),
+    };
+
+    // adbe.pkcs7.sha1 signs a SHA-1 of the range carried as eContent
+    let digest_ok = if sub_filter == Some("adbe.pkcs7.sha1") {
+        data.encap_content_info
+            .econtent
+            .as_ref()
+            .and_then(|c| c.decode_as::<OctetString>().ok())
+            .map(|c| c.as_bytes() == Sha1::digest(signed).as_slice())
+    } else {
+        message_digest(signer_info).map(|md| md == digest)
+    };
+
+    let signer = data
+        .certificates
+        .iter()
+        .flat_map(|set| set.0.iter())
+        .filter_map(|c| match c {
+            CertificateChoices::Certificate(cert) => Some(cert),
+            _ => None,
+        })
+        .find(|cert| is_signer(cert, &signer_info.sid))
+        .map(|cert| cert.tbs_certificate.subject.to_string());
+
+    Ok((algorithm, digest_ok, signer))
+}
+
+fn digest(oid: &str, data: &[u8]) -> Option<(&'static str, Vec<u8>)> {
+    match oid {
+        "1.3.14.3.2.26"          => Some(("sha1", Sha1::digest(data).to_vec())),
+        "2.16.840.1.101.3.4.2.1" => Some(("sha256", Sha256::digest(data).to_vec())),
+        "2.16.840.1.101.3.4.2.2" => Some(("sha384", Sha384::digest(data).to_vec())),
+        "2.16.840.1.101.3.4.2.3" => Some(("sha512", Sha512::digest(data).to_vec())),
+        _ => None,
+    }
+}
+
+fn message_digest(signer_info: &SignerInfo) -> Option<Vec<u8>> {
+    signer_info
+        .signed_attrs
+        .as_ref()?
+        .iter()
+        .find(|attr| attr.oid == ID_MESSAGE_DIGEST)?
+        .values
+        .iter()
+        .next()?
+        .decode_as::<OctetString>()
+        .ok()
+        .map(|md| md.as_bytes().to_vec())
+}
+
+fn is_signer(cert: &Certificate, sid: &SignerIdentifier) -> bool {
+    let tbs = &cert.tbs_certificate;
+    match sid {
+        SignerIdentifier::IssuerAndSerialNumber(isn) => {
+            isn.issuer == tbs.issuer && isn.serial_number == tbs.serial_number
+        }
+        SignerIdentifier::SubjectKeyIdentifier(ski) => tbs
+            .extensions
+            .iter()
+            .flatten()
+            .filter(|ext| ext.extn_id == ID_SUBJECT_KEY_ID)
+            .any(|ext| {
+                SubjectKeyIdentifier::from_der(ext.extn_value.as_bytes())
+                    .is_ok_and(|k| k.to_der().ok() == ski.to_der().ok())
+            }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0‥4 and 10‥14 around the `/Contents` string <0102>
+    const FILE: &[u8] = b"AAAA<0102>BBBB";
+    const CONTENTS: &[u8] = &[1, 2];
+
+    #[test]
+    fn well_formed_range() {
+        let (signed, end) = signed_bytes(FILE, &[0, 4, 10, 4], CONTENTS).unwrap();
+        assert_eq!(signed, b"AAAABBBB");
+        assert_eq!(end, FILE.len());
+        // a revision appended after the signed part
+        let longer = [FILE, b"more"].concat();
+        assert_eq!(signed_bytes(&longer, &[0, 4, 10, 4], CONTENTS).map(|(_, end)| end), Some(14));
+    }
+
+    #[test]
+    fn truncated_ranges() {
+        assert!(signed_bytes(FILE, &[0, 4, 10, 5], CONTENTS).is_none());
+        assert!(signed_bytes(&FILE[..12], &[0, 4, 10, 4], CONTENTS).is_none());
+        assert!(signed_bytes(FILE, &[0, 4, 10], CONTENTS).is_none());
+    }
+
+    #[test]
+    fn overlapping_ranges() {
+        assert!(signed_bytes(FILE, &[0, 6, 4, 4], CONTENTS).is_none());
+        assert!(signed_bytes(FILE, &[1, 4, 10, 4], CONTENTS).is_none());
+        // the gap must be exactly the /Contents string
+        assert!(signed_bytes(FILE, &[0, 4, 9, 5], CONTENTS).is_none());
+        assert!(signed_bytes(FILE, &[0, 3, 10, 4], CONTENTS).is_none());
+    }
+
+    #[test]
+    fn overflowing_ranges() {
+        assert!(signed_bytes(FILE, &[0, 4, i64::MAX, 1], CONTENTS).is_none());
+        assert!(signed_bytes(FILE, &[0, 4, 10, i64::MAX], CONTENTS).is_none());
+        assert!(signed_bytes(FILE, &[0, 4, 10, -4], CONTENTS).is_none());
+        assert!(signed_bytes(FILE, &[0, -1, 10, 4], CONTENTS).is_none());
+        assert!(signed_bytes(FILE, &[0, i64::MIN, 10, 4], CONTENTS).is_none());
+    }
+}
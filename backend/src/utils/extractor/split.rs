@@ -0,0 +1,222 @@
+use anyhow::{bail, Context, Result};
+use lopdf::xref::{Xref, XrefType};
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::HashMap;
+use super::{PageLeaf, Source};
+
+/// Page attributes a one-page file keeps; the ones marked inheritable are
+/// looked up through `/Parent` like a viewer would. Annotations, thumbnails
+/// and structure links point back into the rest of the document and are
+/// dropped.
+const PAGE_KEYS: [(&[u8], bool); 10] = [
+    (b"ArtBox", false), (b"BleedBox", false), (b"Contents", false), (b"CropBox", true),
+    (b"Group", false), (b"MediaBox", true), (b"Resources", true), (b"Rotate", true),
+    (b"TrimBox", false), (b"UserUnit", false),
+];
+
+/// One leaf per page: a standalone one-page PDF, whose bytes are the
+/// preimage. The file is built deterministically — objects renumbered in
+/// traversal order, dictionary keys sorted, streams copied as stored, no
+/// `/ID` or dates — so the same original always yields the same page files
+/// and a page served later by `pdf_single_page` hashes to its leaf.
+pub fn pdf_page_files(bytes: &[u8]) -> Result<Vec<PageLeaf>> {
+    let doc = Document::load_mem(bytes).context("parse PDF")?;
+    let mut out = Vec::new();
+    for (page_no, page_id) in doc.get_pages() {
+        let file = page_file(&doc, page_id).with_context(|| format!("page {page_no}"))?;
+        out.push(PageLeaf::new(file, Source::PagePdf));
+    }
+    Ok(out)
+}
+
+/// The one-page PDF of page `index` (0-based), as committed in page-pdf mode.
+pub fn pdf_single_page(bytes: &[u8], index: usize) -> Result<Vec<u8>> {
+    let doc = Document::load_mem(bytes).context("parse PDF")?;
+    let pages = doc.get_pages();
+    let Some(&page_id) = pages.values().nth(index) else {
+        bail!("page index out of range (0‥{})", pages.len().saturating_sub(1));
+    };
+    page_file(&doc, page_id)
+}
+
+const CATALOG: ObjectId = (1, 0);
+const PAGES:   ObjectId = (2, 0);
+const PAGE:    ObjectId = (3, 0);
+
+fn page_file(doc: &Document, page_id: ObjectId) -> Result<Vec<u8>> {
+    let page = doc.get_dictionary(page_id)?;
+    let mut copier = Copier { doc, ids: HashMap::new(), objects: Vec::new(), next: PAGE.0 + 1, depth: 0 };
+
+    let mut new_page = Dictionary::new();
+    new_page.set("Type", Object::Name(b"Page".to_vec()));
+    new_page.set("Parent", Object::Reference(PAGES));
+    for (key, inheritable) in PAGE_KEYS {
+        let value = if inheritable { inherited(doc, page, key) } else { page.get(key).ok() };
+        if let Some(value) = value {
+            let copied = copier.copy(value)?;
+            new_page.set(key.to_vec(), copied);
+        }
+    }
+
+    let mut out = Document::new();
+    out.version = "1.7".to_owned();
+    out.reference_table = Xref::new(0, XrefType::CrossReferenceTable);
+    let mut catalog = Dictionary::new();
+    catalog.set("Type", Object::Name(b"Catalog".to_vec()));
+    catalog.set("Pages", Object::Reference(PAGES));
+    let mut pages = Dictionary::new();
+    pages.set("Type", Object::Name(b"Pages".to_vec()));
+    pages.set("Kids", Object::Array(vec![Object::Reference(PAGE)]));
+    pages.set("Count", Object::Integer(1));
+
+    out.objects.insert(CATALOG, Object::Dictionary(catalog));
+    out.objects.insert(PAGES, Object::Dictionary(pages));
+    out.objects.insert(PAGE, Object::Dictionary(sorted(&new_page)));
+    out.objects.extend(copier.objects);
+    out.max_id = copier.next - 1;
+    out.trailer.set("Root", Object::Reference(CATALOG));
+
+    let mut file = Vec::new();
+    out.save_to(&mut file).context("write page PDF")?;
+    Ok(file)
+}
+
+fn inherited<'a>(doc: &'a Document, page: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    let mut node = page;
+    for _ in 0..32 {
+        if let Ok(value) = node.get(key) {
+            return Some(value);
+        }
+        node = node
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .ok()?;
+    }
+    None
+}
+
+// Deep copy of everything a page references, renumbered from 4 upwards in
+// the order first reached. `/Parent` links are not followed: they lead
+// back into the source document's page tree. Objects nested deeper than
+// `MAX_NESTING` are an error rather than a stack overflow.
+struct Copier<'a> {
+    doc:     &'a Document,
+    ids:     HashMap<ObjectId, ObjectId>,
+    objects: Vec<(ObjectId, Object)>,
+    next:    u32,
+    depth:   usize,
+}
+
+const MAX_NESTING: usize = 256;
+
+impl Copier<'_> {
+    fn copy(&mut self, obj: &Object) -> Result<Object> {
+        if self.depth == MAX_NESTING {
+            bail!("objects nested more than {MAX_NESTING} levels deep");
+        }
+        self.depth += 1;
+        let copied = self.copy_object(obj);
+        self.depth -= 1;
+        copied
+    }
+
+    fn copy_object(&mut self, obj: &Object) -> Result<Object> {
+        Ok(match obj {
+            Object::Reference(id) => {
+                if let Some(new_id) = self.ids.get(id) {
+                    return Ok(Object::Reference(*new_id));
+                }
+                let new_id = (self.next, 0);
+                self.next += 1;
+                self.ids.insert(*id, new_id);
+                let target = self.doc.get_object(*id).cloned().unwrap_or(Object::Null);
+                let copied = self.copy(&target)?;
+                self.objects.push((new_id, copied));
+                Object::Reference(new_id)
+            }
+            Object::Array(items) => Object::Array(items.iter().map(|o| self.copy(o)).collect::<Result<_>>()?),
+            Object::Dictionary(dict) => Object::Dictionary(self.copy_dict(dict)?),
+            Object::Stream(stream) => {
+                let mut copied = Stream::new(self.copy_dict(&stream.dict)?, stream.content.clone());
+                copied.allows_compression = false;
+                Object::Stream(copied)
+            }
+            other => other.clone(),
+        })
+    }
+
+    fn copy_dict(&mut self, dict: &Dictionary) -> Result<Dictionary> {
+        let mut keys: Vec<&Vec<u8>> = dict.iter().map(|(k, _)| k).filter(|k| k.as_slice() != b"Parent").collect();
+        keys.sort();
+        let mut out = Dictionary::new();
+        for key in keys {
+            if let Ok(value) = dict.get(key) {
+                let copied = self.copy(value)?;
+                out.set(key.clone(), copied);
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn sorted(dict: &Dictionary) -> Dictionary {
+    let mut entries: Vec<(&Vec<u8>, &Object)> = dict.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    let mut out = Dictionary::new();
+    for (k, v) in entries {
+        out.set(k.clone(), v.clone());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle::coreFunctions::generate_hash;
+    use crate::utils::extractor::{extract, Mode, Options, Profile};
+    use crate::utils::stamp::tests::pdf;
+
+    #[test]
+    fn pages_split_the_same_way_every_time() {
+        let original = pdf(3);
+        let opts = Options { mode: Mode::PagePdf, profile: Profile::DEFAULT, chunk_size: 4096, metadata: false, extractor: None };
+        let stored = extract(&original, "doc.pdf", &opts).unwrap();
+        assert_eq!(stored.hashes.len(), 3);
+
+        for (i, leaf_hash) in stored.hashes.iter().enumerate() {
+            let first = pdf_single_page(&original, i).unwrap();
+            let again = pdf_single_page(&original, i).unwrap();
+            assert_eq!(first, again);
+            assert_eq!(&generate_hash(&first), leaf_hash);
+        }
+    }
+
+    #[test]
+    fn long_reference_chains_are_an_error() {
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        // obj 1 → [2 0 R] → [3 0 R] → …, far deeper than the stack allows
+        let first = doc.new_object_id();
+        let mut id = first;
+        for _ in 0..100_000 {
+            let next = doc.new_object_id();
+            doc.objects.insert(id, Object::Array(vec![Object::Reference(next)]));
+            id = next;
+        }
+        doc.objects.insert(id, Object::Null);
+        let page = doc.add_object(Dictionary::from_iter([
+            ("Type", Object::Name(b"Page".to_vec())),
+            ("Parent", Object::Reference(pages_id)),
+            ("Contents", Object::Reference(first)),
+        ]));
+        doc.objects.insert(pages_id, Object::Dictionary(Dictionary::from_iter([
+            ("Type", Object::Name(b"Pages".to_vec())),
+            ("Count", Object::Integer(1)),
+            ("Kids", Object::Array(vec![Object::Reference(page)])),
+        ])));
+
+        let err = page_file(&doc, page).unwrap_err();
+        assert!(err.to_string().contains("nested"), "{err}");
+    }
+}
@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use super::{PageLeaf, Source};
+
+/// True for bytes that parse as a JSON object or array; a top-level
+/// scalar is JSON only by name, see `Format::detect_named`.
+pub(super) fn looks_like_json(bytes: &[u8]) -> bool {
+    let body = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    matches!(body.iter().find(|b| !b.is_ascii_whitespace()), Some(b'{' | b'['))
+        && serde_json::from_slice::<Value>(body).is_ok()
+}
+
+/// One leaf per scalar (and per empty object/array), keyed by its RFC 6901
+/// JSON Pointer. Object members are visited in key order, so reordering
+/// keys or reformatting the file keeps every leaf. The preimage is the
+/// compact JSON `["json-v1", pointer, value]`, which is what gets disclosed.
+pub fn json_to_leaves(bytes: &[u8]) -> Result<Vec<PageLeaf>> {
+    let body = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    let value: Value = serde_json::from_slice(body).context("parse JSON")?;
+    let mut out = Vec::new();
+    walk(&value, String::new(), &mut out);
+    Ok(out)
+}
+
+fn walk(value: &Value, pointer: String, out: &mut Vec<PageLeaf>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            // sorted explicitly: with `preserve_order` (Cargo.toml) maps
+            // keep file order
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (k, v) in entries {
+                walk(v, format!("{pointer}/{}", escape_pointer(k)), out);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (i, v) in items.iter().enumerate() {
+                walk(v, format!("{pointer}/{i}"), out);
+            }
+        }
+        _ => {
+            let payload = json!(["json-v1", pointer, value]).to_string().into_bytes();
+            out.push(PageLeaf::keyed(pointer, payload, Source::Field));
+        }
+    }
+}
+
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// One leaf per data row, keyed by its 0-based row number. The header is
+/// bound into every leaf — `["csv-v1", header, row]` — so a disclosed row
+/// also proves which column each value belongs to.
+pub fn csv_to_leaves(bytes: &[u8]) -> Result<Vec<PageLeaf>> {
+    let body = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .from_reader(body);
+    let header: Vec<String> = reader
+        .headers()
+        .context("read CSV header")?
+        .iter()
+        .map(str::to_owned)
+        .collect();
+
+    let mut out = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record.with_context(|| format!("read CSV row {i}"))?;
+        let row: Vec<&str> = record.iter().collect();
+        let payload = json!(["csv-v1", header, row]).to_string().into_bytes();
+        out.push(PageLeaf::keyed(i.to_string(), payload, Source::Row));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::extractor::Format;
+
+    fn leaves(leaves: &[PageLeaf]) -> Vec<(&str, &str)> {
+        leaves
+            .iter()
+            .map(|l| (l.key.as_deref().unwrap(), std::str::from_utf8(&l.payload).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn key_order_and_layout_do_not_matter() {
+        let a = json_to_leaves(br#"{"b": [1, {"c": null}], "a": "x"}"#).unwrap();
+        let b = json_to_leaves(b"\xEF\xBB\xBF{\n  \"a\": \"x\",\n  \"b\": [1, {\"c\": null}]\n}").unwrap();
+        assert_eq!(leaves(&a), leaves(&b));
+        assert_eq!(leaves(&a), [
+            ("/a", r#"["json-v1","/a","x"]"#),
+            ("/b/0", r#"["json-v1","/b/0",1]"#),
+            ("/b/1/c", r#"["json-v1","/b/1/c",null]"#),
+        ]);
+    }
+
+    #[test]
+    fn pointers_are_escaped_and_empty_containers_are_leaves() {
+        let out = json_to_leaves(br#"{"a/b": {}, "m~n": [], "": 0}"#).unwrap();
+        assert_eq!(leaves(&out), [
+            ("/", r#"["json-v1","/",0]"#),
+            ("/a~1b", r#"["json-v1","/a~1b",{}]"#),
+            ("/m~0n", r#"["json-v1","/m~0n",[]]"#),
+        ]);
+    }
+
+    #[test]
+    fn scalars_are_json_by_name_only() {
+        assert!(looks_like_json(b" [1]"));
+        assert!(!looks_like_json(b"42"));
+        assert!(!looks_like_json(b"{ not json"));
+        assert_eq!(Format::detect_named(b"42", "n.JSON"), Format::Json);
+        assert_eq!(Format::detect_named(b"42", "n.txt"), Format::Binary);
+        assert_eq!(leaves(&json_to_leaves(b"\"s\"").unwrap()), [("", r#"["json-v1","","s"]"#)]);
+    }
+
+    #[test]
+    fn rows_carry_their_header() {
+        let out = csv_to_leaves(b"\xEF\xBB\xBFname,qty\r\npen,2\n\"a, b\",3,extra\n").unwrap();
+        assert_eq!(leaves(&out), [
+            ("0", r#"["csv-v1",["name","qty"],["pen","2"]]"#),
+            ("1", r#"["csv-v1",["name","qty"],["a, b","3","extra"]]"#),
+        ]);
+        assert!(csv_to_leaves(b"name,qty\n").unwrap().is_empty());
+    }
+}
@@ -1 +1,4 @@
+pub mod canonical;
 pub mod extractor;
+pub mod spool;
+pub mod stamp;
@@ -0,0 +1,100 @@
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::ops::Deref;
+
+/// Bodies up to this size stay in memory.
+pub const MEMORY_LIMIT: usize = 8 * 1024 * 1024;
+
+/// An upload body being received: kept in memory while small, moved to an
+/// anonymous temp file past `MEMORY_LIMIT`.
+pub struct Spool {
+    len:   usize,
+    state: State,
+}
+
+enum State {
+    Memory(Vec<u8>),
+    Disk(BufWriter<File>),
+}
+
+/// A received body. On disk it is memory-mapped, so extractors still see
+/// one `&[u8]` while the OS pages it in instead of it being copied onto
+/// the heap.
+pub enum Spooled {
+    Memory(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl Spool {
+    pub fn new() -> Self {
+        Spool { len: 0, state: State::Memory(Vec::new()) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.len += data.len();
+        if let State::Memory(buf) = &mut self.state {
+            if self.len <= MEMORY_LIMIT {
+                buf.extend_from_slice(data);
+                return Ok(());
+            }
+            let mut file = BufWriter::new(tempfile::tempfile()?);
+            file.write_all(buf)?;
+            self.state = State::Disk(file);
+        }
+        match &mut self.state {
+            State::Disk(file) => file.write_all(data),
+            State::Memory(_)  => unreachable!(),
+        }
+    }
+
+    pub fn finish(self) -> io::Result<Spooled> {
+        match self.state {
+            State::Memory(buf) => Ok(Spooled::Memory(buf)),
+            State::Disk(file)  => {
+                let file = file.into_inner().map_err(|e| e.into_error())?;
+                // SAFETY: the file is unnamed and only ever held here, so
+                // nothing can change it while the map is alive
+                let map = unsafe { Mmap::map(&file)? };
+                Ok(Spooled::Mapped(map))
+            }
+        }
+    }
+}
+
+impl Spooled {
+    /// A body already received into a file, held the same way.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may write to or truncate the file while the result is alive.
+    pub unsafe fn open(path: &Path) -> io::Result<Spooled> {
+        let file = File::open(path)?;
+        if file.metadata()?.len() <= MEMORY_LIMIT as u64 {
+            return std::fs::read(path).map(Spooled::Memory);
+        }
+        Ok(Spooled::Mapped(Mmap::map(&file)?))
+    }
+}
+
+impl Deref for Spooled {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Spooled::Memory(buf) => buf,
+            Spooled::Mapped(map) => map,
+        }
+    }
+}
+
+impl AsRef<[u8]> for Spooled {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
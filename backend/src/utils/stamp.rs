@@ -0,0 +1,393 @@
+use anyhow::{bail, Context, Result};
+use lopdf::{Dictionary, Document, IncrementalDocument, Object, ObjectId, Stream, StringFormat};
+use qrcode::{Color, EcLevel, QrCode};
+
+/// Trailer key of a stamp revision: `<< /Length <bytes before it> /RootHash (…) >>`.
+const STAMP_KEY: &[u8] = b"/MerkleStamp";
+/// Name of the attached proof file.
+pub const PROOFS_FILE: &str = "proofs.json";
+
+// QR side length in points, and its distance from the page corner
+const QR_SIZE:   f32 = 72.0;
+const QR_MARGIN: f32 = 12.0;
+// light modules around the code, as the QR spec asks for
+const QUIET_ZONE: usize = 4;
+
+/// What a stamp adds to a PDF.
+pub struct Stamp {
+    pub root_hash:  String,
+    /// contents of the `proofs.json` attachment
+    pub proofs:     Vec<u8>,
+    /// text of the QR code drawn on each page, by page index
+    pub page_codes: Vec<String>,
+}
+
+/// The PDF with a stamp appended as an incremental update: `proofs.json`
+/// as an embedded file and a QR code on every page as a Stamp annotation.
+/// The original bytes stay in front untouched, so embedded signatures
+/// still validate, and no leaf is computed from annotations or
+/// attachments, so the stamped copy extracts to the same root.
+pub fn stamp_pdf(bytes: &[u8], stamp: &Stamp) -> Result<Vec<u8>> {
+    let prev = Document::load_mem(bytes).context("parse PDF")?;
+    if prev.trailer.has(b"Encrypt") {
+        bail!("encrypted PDFs can't be stamped");
+    }
+    let pages: Vec<ObjectId> = prev.get_pages().into_values().collect();
+    if stamp.page_codes.len() > pages.len() {
+        bail!("{} QR codes for {} pages", stamp.page_codes.len(), pages.len());
+    }
+    let catalog_id = prev.trailer.get(b"Root").and_then(Object::as_reference).context("no catalog")?;
+
+    let mut inc = IncrementalDocument::create_from(bytes.to_vec(), prev.clone());
+    let new = &mut inc.new_document;
+
+    // ── 1. a QR annotation per page ──────────────────────────────────────
+    for (page_id, code) in pages.iter().zip(&stamp.page_codes) {
+        let mut page = prev.get_dictionary(*page_id)?.clone();
+        let [_, y0, x1, _] = page_box(&prev, &page);
+        let rect = [x1 - QR_MARGIN - QR_SIZE, y0 + QR_MARGIN, x1 - QR_MARGIN, y0 + QR_MARGIN + QR_SIZE];
+
+        let appearance = new.add_object(qr_appearance(code)?);
+        let mut annot = Dictionary::new();
+        annot.set("Type", Object::Name(b"Annot".to_vec()));
+        annot.set("Subtype", Object::Name(b"Stamp".to_vec()));
+        annot.set("Rect", Object::Array(rect.iter().map(|v| Object::Real(*v)).collect()));
+        annot.set("F", Object::Integer(4 | 128));   // print, locked
+        annot.set("NM", Object::string_literal("merkle-stamp"));
+        annot.set("Contents", Object::string_literal(code.as_str()));
+        annot.set("AP", Object::Dictionary(Dictionary::from_iter([("N", Object::Reference(appearance))])));
+        let annot = new.add_object(annot);
+
+        // an /Annots array held in its own object is updated in place
+        match page.get(b"Annots") {
+            Ok(Object::Reference(id)) => {
+                let id = *id;
+                let mut annots = prev.get_object(id).and_then(Object::as_array).cloned().unwrap_or_default();
+                annots.push(Object::Reference(annot));
+                new.set_object(id, Object::Array(annots));
+            }
+            Ok(Object::Array(annots)) => {
+                let mut annots = annots.clone();
+                annots.push(Object::Reference(annot));
+                page.set("Annots", Object::Array(annots));
+                new.set_object(*page_id, page);
+            }
+            _ => {
+                page.set("Annots", Object::Array(vec![Object::Reference(annot)]));
+                new.set_object(*page_id, page);
+            }
+        }
+    }
+
+    // ── 2. proofs.json in the catalog's /EmbeddedFiles ───────────────────
+    let mut file = Stream::new(Dictionary::new(), stamp.proofs.clone());
+    file.dict.set("Type", Object::Name(b"EmbeddedFile".to_vec()));
+    file.dict.set("Subtype", Object::Name(b"application/json".to_vec()));
+    file.compress()?;
+    let file = new.add_object(file);
+    let mut spec = Dictionary::new();
+    spec.set("Type", Object::Name(b"Filespec".to_vec()));
+    spec.set("F", Object::string_literal(PROOFS_FILE));
+    spec.set("UF", Object::string_literal(PROOFS_FILE));
+    spec.set("Desc", Object::string_literal("Merkle proofs of every leaf"));
+    spec.set("EF", Object::Dictionary(Dictionary::from_iter([("F", Object::Reference(file))])));
+    let spec = new.add_object(spec);
+    attach(&prev, new, catalog_id, spec)?;
+
+    inc.new_document.trailer.set(
+        "MerkleStamp",
+        Dictionary::from_iter([
+            ("Length", Object::Integer(bytes.len() as i64)),
+            ("RootHash", Object::String(stamp.root_hash.as_bytes().to_vec(), StringFormat::Literal)),
+        ]),
+    );
+    let mut out = Vec::new();
+    inc.save_to(&mut out).context("write stamped PDF")?;
+    Ok(out)
+}
+
+/// Length of the file before any stamp revisions, i.e. the bytes that
+/// were uploaded. A trailing revision only counts as a stamp if
+/// `stamp_pdf` run again on the bytes before it, with the root, QR texts
+/// and proofs read back from it, writes exactly that revision; anything
+/// else, however it is labelled, stays part of the file.
+pub fn original_len(bytes: &[u8]) -> usize {
+    let mut end = bytes.len();
+    while let Some(len) = stamp_start(&bytes[..end]) {
+        if !is_our_stamp(&bytes[..len], &bytes[..end]) {
+            break;
+        }
+        end = len;
+    }
+    end
+}
+
+// `/MerkleStamp<</Length n` in the trailer of the last revision, with n
+// pointing just past an earlier %%EOF
+fn stamp_start(bytes: &[u8]) -> Option<usize> {
+    let at = rfind(bytes, STAMP_KEY)?;
+    if bytes[at..].windows(5).filter(|w| *w == b"%%EOF").count() != 1 {
+        return None;
+    }
+    let rest = bytes[at + STAMP_KEY.len()..].strip_prefix(b"<</Length ")?;
+    let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+    let len: usize = std::str::from_utf8(&rest[..digits]).ok()?.parse().ok()?;
+    let head = bytes.get(..len)?;
+    head.trim_ascii_end().ends_with(b"%%EOF").then_some(len)
+}
+
+fn is_our_stamp(before: &[u8], after: &[u8]) -> bool {
+    let (Ok(prev), Ok(doc)) = (Document::load_mem(before), Document::load_mem(after)) else {
+        return false;
+    };
+    let Some(stamp) = read_stamp(&prev, &doc) else { return false };
+    stamp_pdf(before, &stamp).is_ok_and(|again| again == after)
+}
+
+// What `stamp_pdf` was given, from the objects the revision added: its
+// annotations in the order they were written, the one embedded file and
+// the trailer's root.
+fn read_stamp(prev: &Document, doc: &Document) -> Option<Stamp> {
+    let root_hash = match doc.trailer.get(b"MerkleStamp").and_then(Object::as_dict).ok()?.get(b"RootHash") {
+        Ok(Object::String(root, _)) => String::from_utf8(root.clone()).ok()?,
+        _ => return None,
+    };
+    let mut page_codes = Vec::new();
+    let mut proofs = None;
+    for (id, obj) in doc.objects.iter().filter(|(id, _)| !prev.objects.contains_key(id)) {
+        match obj {
+            Object::Dictionary(dict) if dict.get(b"Subtype").ok() == Some(&Object::Name(b"Stamp".to_vec())) => {
+                match dict.get(b"Contents") {
+                    Ok(Object::String(code, _)) => page_codes.push((*id, String::from_utf8(code.clone()).ok()?)),
+                    _ => return None,
+                }
+            }
+            Object::Stream(stream) if stream.dict.get(b"Type").ok() == Some(&Object::Name(b"EmbeddedFile".to_vec())) => {
+                if proofs.is_some() {
+                    return None;
+                }
+                // `compress` leaves short files as they are
+                proofs = Some(match stream.dict.has(b"Filter") {
+                    true  => stream.decompressed_content().ok()?,
+                    false => stream.content.clone(),
+                });
+            }
+            _ => {}
+        }
+    }
+    page_codes.sort_by_key(|(id, _)| *id);
+    Some(Stamp {
+        root_hash,
+        proofs:     proofs?,
+        page_codes: page_codes.into_iter().map(|(_, code)| code).collect(),
+    })
+}
+
+// Hook the file spec into /Names/EmbeddedFiles, keeping existing entries.
+// A flat /Names array is merged in sorted order; a tree with /Kids gets one
+// more kid.
+fn attach(prev: &Document, new: &mut Document, catalog_id: ObjectId, spec: ObjectId) -> Result<()> {
+    let mut catalog = prev.get_dictionary(catalog_id)?.clone();
+    let (names_id, mut names) = match catalog.get(b"Names") {
+        Ok(Object::Reference(id)) => (Some(*id), prev.get_dictionary(*id)?.clone()),
+        Ok(Object::Dictionary(d)) => (None, d.clone()),
+        _ => (None, Dictionary::new()),
+    };
+    let mut tree = match names.get(b"EmbeddedFiles") {
+        Ok(o) => prev.dereference(o).and_then(|(_, o)| o.as_dict()).cloned().unwrap_or_default(),
+        Err(_) => Dictionary::new(),
+    };
+    let entry = [Object::string_literal(PROOFS_FILE), Object::Reference(spec)];
+
+    if let Ok(kids) = tree.get(b"Kids").and_then(Object::as_array) {
+        let mut kids = kids.clone();
+        let mut leaf = Dictionary::new();
+        leaf.set("Names", Object::Array(entry.to_vec()));
+        leaf.set("Limits", Object::Array(vec![entry[0].clone(), entry[0].clone()]));
+        kids.push(Object::Reference(new.add_object(leaf)));
+        tree.set("Kids", Object::Array(kids));
+    } else {
+        let mut pairs: Vec<Vec<Object>> = match tree.get(b"Names").and_then(Object::as_array) {
+            Ok(flat) => flat.chunks_exact(2).map(<[Object]>::to_vec).collect(),
+            Err(_) => Vec::new(),
+        };
+        pairs.push(entry.to_vec());
+        pairs.sort_by(|a, b| a[0].as_str().unwrap_or_default().cmp(b[0].as_str().unwrap_or_default()));
+        tree.set("Names", Object::Array(pairs.concat()));
+    }
+    names.set("EmbeddedFiles", Object::Reference(new.add_object(tree)));
+
+    match names_id {
+        Some(id) => new.set_object(id, names),
+        None => {
+            catalog.set("Names", Object::Dictionary(names));
+            new.set_object(catalog_id, catalog);
+        }
+    }
+    Ok(())
+}
+
+// CropBox, else MediaBox, else Letter — both inheritable from /Parent
+fn page_box(doc: &Document, page: &Dictionary) -> [f32; 4] {
+    for key in [b"CropBox".as_slice(), b"MediaBox"] {
+        let mut node = Some(page);
+        for _ in 0..32 {
+            let Some(dict) = node else { break };
+            if let Ok(arr) = dict.get(key).and_then(|o| doc.dereference(o)).and_then(|(_, o)| o.as_array()) {
+                let nums: Vec<f32> = arr.iter().filter_map(|o| o.as_float().ok()).collect();
+                if let Ok([a, b, c, d]) = <[f32; 4]>::try_from(nums) {
+                    return [a.min(c), b.min(d), a.max(c), b.max(d)];
+                }
+            }
+            node = dict.get(b"Parent").and_then(Object::as_reference).and_then(|id| doc.get_dictionary(id)).ok();
+        }
+    }
+    [0.0, 0.0, 612.0, 792.0]
+}
+
+// Form XObject in module units, dark runs of each row as one rectangle; the
+// viewer scales the /BBox onto the annotation's /Rect
+fn qr_appearance(text: &str) -> Result<Stream> {
+    let code = QrCode::with_error_correction_level(text, EcLevel::L).context("QR code")?;
+    let width = code.width();
+    let side = width + 2 * QUIET_ZONE;
+    let colors = code.to_colors();
+
+    let mut ops = format!("1 g 0 0 {side} {side} re f 0 g\n");
+    for (row, line) in colors.chunks(width).enumerate() {
+        let y = side - QUIET_ZONE - row - 1;
+        let mut col = 0;
+        while col < width {
+            if line[col] == Color::Dark {
+                let run = line[col..].iter().take_while(|c| **c == Color::Dark).count();
+                ops.push_str(&format!("{} {y} {run} 1 re\n", col + QUIET_ZONE));
+                col += run;
+            } else {
+                col += 1;
+            }
+        }
+    }
+    ops.push_str("f\n");
+
+    let mut dict = Dictionary::new();
+    dict.set("Type", Object::Name(b"XObject".to_vec()));
+    dict.set("Subtype", Object::Name(b"Form".to_vec()));
+    dict.set("BBox", Object::Array(vec![0.into(), 0.into(), (side as i64).into(), (side as i64).into()]));
+    let mut stream = Stream::new(dict, ops.into_bytes());
+    stream.compress()?;
+    Ok(stream)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn pdf(n_pages: usize) -> Vec<u8> {
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let kids: Vec<Object> = (0..n_pages)
+            .map(|_| {
+                let content = doc.add_object(Stream::new(Dictionary::new(), b"0 0 10 10 re f".to_vec()));
+                let page = doc.add_object(Dictionary::from_iter([
+                    ("Type", Object::Name(b"Page".to_vec())),
+                    ("Parent", Object::Reference(pages_id)),
+                    ("Contents", Object::Reference(content)),
+                ]));
+                Object::Reference(page)
+            })
+            .collect();
+        doc.objects.insert(pages_id, Object::Dictionary(Dictionary::from_iter([
+            ("Type", Object::Name(b"Pages".to_vec())),
+            ("Count", Object::Integer(n_pages as i64)),
+            ("MediaBox", Object::Array(vec![0.into(), 0.into(), 612.into(), 792.into()])),
+            ("Kids", Object::Array(kids)),
+        ])));
+        let catalog = doc.add_object(Dictionary::from_iter([
+            ("Type", Object::Name(b"Catalog".to_vec())),
+            ("Pages", Object::Reference(pages_id)),
+        ]));
+        doc.trailer.set("Root", Object::Reference(catalog));
+        let mut out = Vec::new();
+        doc.save_to(&mut out).unwrap();
+        out
+    }
+
+    fn stamp(n_pages: usize) -> Stamp {
+        Stamp {
+            root_hash:  "ab".repeat(32),
+            proofs:     br#"{"leaves":[]}"#.to_vec(),
+            page_codes: (0..n_pages).map(|i| format!("https://example.org/p/{i}")).collect(),
+        }
+    }
+
+    // an incremental update labelled as a stamp of `bytes`
+    fn labelled(bytes: &[u8], edit: impl FnOnce(&Document, &mut Document)) -> Vec<u8> {
+        let prev = Document::load_mem(bytes).unwrap();
+        let mut inc = IncrementalDocument::create_from(bytes.to_vec(), prev.clone());
+        edit(&prev, &mut inc.new_document);
+        inc.new_document.trailer.set(
+            "MerkleStamp",
+            Dictionary::from_iter([
+                ("Length", Object::Integer(bytes.len() as i64)),
+                ("RootHash", Object::string_literal("ab".repeat(32))),
+            ]),
+        );
+        let mut out = Vec::new();
+        inc.save_to(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn unstamped_file_is_whole() {
+        let bytes = pdf(2);
+        assert_eq!(original_len(&bytes), bytes.len());
+    }
+
+    #[test]
+    fn stamps_are_recognised() {
+        let bytes = pdf(3);
+        let once = stamp_pdf(&bytes, &stamp(3)).unwrap();
+        assert_eq!(original_len(&once), bytes.len());
+        // stamping a stamped copy again peels back to the upload
+        let twice = stamp_pdf(&once, &stamp(3)).unwrap();
+        assert_eq!(original_len(&twice), bytes.len());
+        // codes on only some of the pages
+        let partial = stamp_pdf(&bytes, &stamp(1)).unwrap();
+        assert_eq!(original_len(&partial), bytes.len());
+    }
+
+    #[test]
+    fn labelled_revisions_that_change_pages_are_kept() {
+        let bytes = pdf(2);
+        let forged = labelled(&bytes, |prev, new| {
+            let page_id = *prev.get_pages().get(&1).unwrap();
+            let mut page = prev.get_dictionary(page_id).unwrap().clone();
+            let content = new.add_object(Stream::new(Dictionary::new(), b"0 0 20 20 re f".to_vec()));
+            page.set("Contents", Object::Reference(content));
+            new.set_object(page_id, page);
+        });
+        assert_eq!(original_len(&forged), forged.len());
+    }
+
+    #[test]
+    fn altered_stamps_are_kept() {
+        let bytes = pdf(2);
+        let mut stamped = stamp_pdf(&bytes, &stamp(2)).unwrap();
+        // the first code moved over the page, same length so the xref holds
+        let at = bytes.len() + rfind(&stamped[bytes.len()..], b"/Rect[528").unwrap();
+        stamped[at + 6] = b'1';
+        assert_eq!(original_len(&stamped), stamped.len());
+    }
+
+    #[test]
+    fn bytes_after_a_stamp_count() {
+        let bytes = pdf(1);
+        let mut stamped = stamp_pdf(&bytes, &stamp(1)).unwrap();
+        stamped.extend_from_slice(b"% trailing\n");
+        assert_eq!(original_len(&stamped), stamped.len());
+    }
+}